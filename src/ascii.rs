@@ -0,0 +1,245 @@
+use crate::{
+    board::Board,
+    tile::{Tile, TileType, MATERIALS},
+};
+
+/// Characters used when a board file doesn't say otherwise.
+pub const DEFAULT_LEGEND: &[(char, TileType)] = &[
+    ('.', TileType::None),
+    ('#', TileType::Wall),
+    ('s', TileType::Sand),
+    ('r', TileType::Rock),
+    ('~', TileType::Water),
+    ('f', TileType::Fire),
+    ('w', TileType::Wood),
+    ('a', TileType::Acid),
+    ('d', TileType::Dirt),
+];
+
+/// Which character stands for which tile type.
+#[derive(Clone, Debug)]
+pub struct Legend {
+    entries: Vec<(char, TileType)>,
+}
+
+impl Default for Legend {
+    fn default() -> Self {
+        Legend {
+            entries: DEFAULT_LEGEND.to_vec(),
+        }
+    }
+}
+
+impl Legend {
+    pub fn tile_type(&self, symbol: char) -> Option<TileType> {
+        self.entries
+            .iter()
+            .find(|(c, _)| *c == symbol)
+            .map(|(_, tile_type)| *tile_type)
+    }
+
+    pub fn symbol(&self, tile_type: TileType) -> Option<char> {
+        self.entries
+            .iter()
+            .find(|(_, t)| *t == tile_type)
+            .map(|(c, _)| *c)
+    }
+
+    /// Maps `symbol` to `tile_type`, replacing whatever it meant before.
+    pub fn insert(&mut self, symbol: char, tile_type: TileType) {
+        self.entries.retain(|(c, _)| *c != symbol);
+        self.entries.insert(0, (symbol, tile_type));
+    }
+
+    /// Reads a `@ <char> <TileType>` header line.
+    fn parse_entry(&mut self, line: &str) -> Result<(), String> {
+        let mut parts = line.split_whitespace();
+        let symbol = parts.next().and_then(|part| {
+            let mut chars = part.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        });
+        let name = parts.next();
+
+        match (symbol, name, parts.next()) {
+            (Some(symbol), Some(name), None) => {
+                let tile_type = tile_type_from_name(name)
+                    .ok_or_else(|| format!("unknown tile type '{}'", name))?;
+                self.insert(symbol, tile_type);
+                Ok(())
+            }
+            _ => Err("expected '@ <character> <tile type>'".into()),
+        }
+    }
+}
+
+pub fn tile_type_from_name(name: &str) -> Option<TileType> {
+    MATERIALS
+        .iter()
+        .map(|mat| mat.tile_type)
+        .find(|tile_type| format!("{:?}", tile_type) == name)
+}
+
+impl Board {
+    /// Parses a text board: optional `@ <char> <TileType>` legend lines, then one line
+    /// per row with the top row first. Surrounding whitespace and blank lines are ignored,
+    /// so boards can be written indented in source.
+    pub fn from_ascii(text: &str) -> Result<Self, String> {
+        let mut legend = Legend::default();
+        let mut rows: Vec<(usize, &str)> = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(entry) = line.strip_prefix('@') {
+                if !rows.is_empty() {
+                    return Err(format!(
+                        "line {}: legend lines must come before the board",
+                        line_number
+                    ));
+                }
+                legend
+                    .parse_entry(entry)
+                    .map_err(|err| format!("line {}: {}", line_number, err))?;
+            } else {
+                rows.push((line_number, line));
+            }
+        }
+
+        let height = rows.len();
+        let width = rows.first().map_or(0, |(_, row)| row.chars().count());
+        if width == 0 {
+            return Err("the board is empty".into());
+        }
+
+        let mut tiles = vec![vec![Tile::from_type(TileType::None); height]; width];
+
+        for (row_index, (line_number, row)) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(format!(
+                    "line {}: expected {} tiles, found {}",
+                    line_number,
+                    width,
+                    row.chars().count()
+                ));
+            }
+
+            let y = height - 1 - row_index;
+            for (x, c) in row.chars().enumerate() {
+                let tile_type = legend
+                    .tile_type(c)
+                    .ok_or_else(|| format!("line {}: '{}' isn't in the legend", line_number, c))?;
+                tiles[x][y] = Tile::from_type(tile_type);
+            }
+        }
+
+        Ok(Board {
+            tiles,
+            dirty_tiles: Vec::new(),
+        })
+    }
+
+    /// Prints the board in the format `from_ascii` reads, using the default legend.
+    pub fn to_ascii(&self) -> String {
+        let legend = Legend::default();
+        let mut text = String::with_capacity((self.width() + 1) * self.height());
+
+        for y in (0..self.height()).rev() {
+            for x in 0..self.width() {
+                // Every tile type is in the default legend.
+                text.push(legend.symbol(self.tiles[x][y].tile_type).unwrap_or('?'));
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "\
+#..~~#
+#s.rf#
+#wad.#
+######
+";
+        let board = Board::from_ascii(text).unwrap();
+        assert_eq!((board.width(), board.height()), (6, 4));
+        assert_eq!(board.to_ascii(), text);
+    }
+
+    #[test]
+    fn top_row_comes_first() {
+        let board = Board::from_ascii("s.\n.#").unwrap();
+        assert_eq!(board.tiles[0][1].tile_type, TileType::Sand);
+        assert_eq!(board.tiles[1][0].tile_type, TileType::Wall);
+        assert_eq!(board.tiles[0][0].tile_type, TileType::None);
+    }
+
+    #[test]
+    fn indented_with_legend() {
+        let board = Board::from_ascii(
+            "
+            @ W Water
+            @ . Sand
+
+            WW
+            ..
+            ",
+        )
+        .unwrap();
+        assert_eq!(board.to_ascii(), "~~\nss\n");
+    }
+
+    #[test]
+    fn every_tile_type_has_a_symbol() {
+        let legend = Legend::default();
+        for mat in MATERIALS.iter() {
+            let symbol = legend.symbol(mat.tile_type).unwrap();
+            assert_eq!(legend.tile_type(symbol), Some(mat.tile_type));
+            assert_eq!(
+                tile_type_from_name(&format!("{:?}", mat.tile_type)),
+                Some(mat.tile_type)
+            );
+        }
+    }
+
+    #[test]
+    fn legend_errors() {
+        let error = |text: &str| Board::from_ascii(text).unwrap_err();
+        assert_eq!(error("@ x Lava\nx"), "line 1: unknown tile type 'Lava'");
+        assert_eq!(
+            error("@ xy Sand\n."),
+            "line 1: expected '@ <character> <tile type>'"
+        );
+        assert_eq!(
+            error("@ x Sand Water\n."),
+            "line 1: expected '@ <character> <tile type>'"
+        );
+        assert_eq!(
+            error("..\n@ x Sand"),
+            "line 2: legend lines must come before the board"
+        );
+        assert_eq!(error("..\n.?"), "line 2: '?' isn't in the legend");
+    }
+
+    #[test]
+    fn shape_errors() {
+        let error = |text: &str| Board::from_ascii(text).unwrap_err();
+        assert_eq!(error(""), "the board is empty");
+        assert_eq!(error("@ x Sand\n"), "the board is empty");
+        assert_eq!(error("...\n..\n..."), "line 2: expected 3 tiles, found 2");
+    }
+}
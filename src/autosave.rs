@@ -0,0 +1,285 @@
+use crate::{
+    board::Board,
+    consts::AUTOSAVE_PATH,
+    controls::UndoHistory,
+    multiplayer::spawn_runtime,
+    settings::{write_atomic, Settings, SettingsPath},
+    timeline::Timeline,
+};
+use bevy::{app::AppExit, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+/// Written on startup with the process id and removed on a clean exit, so finding it on
+/// startup means the last session crashed or was killed, or is still running.
+const LOCK_FILE: &str = "session.lock";
+
+pub struct AutosavePlugin;
+
+impl Plugin for AutosavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(Update, (autosave, restore_window))
+            .add_systems(Last, clean_exit);
+    }
+}
+
+/// Saves the board every `autosave.interval` seconds to `autosave-0.board`,
+/// `autosave-1.board`, ... next to the settings file, going round `autosave.keep` of
+/// them. The files are written on a tokio thread so a frame never waits for the disk.
+#[derive(Resource)]
+struct Autosave {
+    dir: PathBuf,
+    saves: UnboundedSender<(PathBuf, Board)>,
+    next_slot: usize,
+    /// Real time of the last autosave, or of startup.
+    last_save: Duration,
+    /// The newest autosave of a session that didn't exit cleanly, and when it was
+    /// written. Nothing is autosaved until it's restored or dismissed, so it can't be
+    /// overwritten first.
+    offer: Option<(PathBuf, SystemTime)>,
+    /// Why the last restore failed.
+    error: Option<String>,
+}
+
+fn slot_path(dir: &Path, slot: usize) -> PathBuf {
+    dir.join(format!("autosave-{}.board", slot))
+}
+
+/// The slot, path and modification time of the most recently written autosave.
+fn newest_autosave(dir: &Path) -> Option<(usize, PathBuf, SystemTime)> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let slot = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("autosave-")?
+                .strip_suffix(".board")?
+                .parse()
+                .ok()?;
+            let modified = path.metadata().ok()?.modified().ok()?;
+            Some((slot, path, modified))
+        })
+        .max_by_key(|(_, _, modified)| *modified)
+}
+
+/// Whether `lock` was left by a session that's no longer running.
+fn stale_lock(lock: &Path) -> bool {
+    let Ok(contents) = fs::read_to_string(lock) else {
+        return lock.exists();
+    };
+    match contents.trim().parse::<u32>() {
+        Ok(pid) => !process_running(pid),
+        Err(_) => true,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Without a cheap way to check, assume the session is gone, as a second copy running
+/// at once is the rarer case.
+#[cfg(not(target_os = "linux"))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
+
+fn setup(mut commands: Commands, path: Res<SettingsPath>) {
+    let dir = path.sibling(AUTOSAVE_PATH);
+    let lock = dir.join(LOCK_FILE);
+    let unclean = stale_lock(&lock);
+    let newest = newest_autosave(&dir);
+
+    if let Err(err) = write_atomic(&lock, std::process::id().to_string().as_bytes()) {
+        eprintln!("Couldn't write {}: {}", lock.display(), err);
+    }
+
+    let (saves, mut receiver) = unbounded_channel::<(PathBuf, Board)>();
+    spawn_runtime(async move {
+        while let Some((path, board)) = receiver.recv().await {
+            // Encoding a big board and syncing the file both block, so keep them off
+            // the runtime's own threads.
+            let result = tokio::task::spawn_blocking(move || {
+                write_atomic(&path, &board.to_binary())
+                    .map_err(|err| format!("Couldn't autosave to {}: {}", path.display(), err))
+            })
+            .await;
+            if let Ok(Err(err)) = result {
+                eprintln!("{}", err);
+            }
+        }
+    });
+
+    commands.insert_resource(Autosave {
+        dir,
+        saves,
+        next_slot: newest.as_ref().map_or(0, |(slot, _, _)| slot + 1),
+        last_save: Duration::ZERO,
+        offer: newest
+            .filter(|_| unclean)
+            .map(|(_, path, modified)| (path, modified)),
+        error: None,
+    });
+}
+
+fn autosave(
+    board: Res<Board>,
+    settings: Res<Settings>,
+    time: Res<Time<Real>>,
+    mut autosave: ResMut<Autosave>,
+) {
+    let now = time.elapsed();
+    if !settings.autosave.enabled
+        || autosave.offer.is_some()
+        || now - autosave.last_save < Duration::from_secs(settings.autosave.interval)
+    {
+        return;
+    }
+    autosave.last_save = now;
+
+    let slot = autosave.next_slot % settings.autosave.keep;
+    autosave.next_slot = slot + 1;
+    let copy = Board {
+        tiles: board.tiles.clone(),
+        dirty_tiles: Vec::new(),
+    };
+    let _ = autosave.saves.send((slot_path(&autosave.dir, slot), copy));
+}
+
+/// "3 minutes", "1 hour" and so on.
+fn describe_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (amount, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{} {}{}", amount, unit, plural)
+}
+
+fn restore_window(
+    mut contexts: EguiContexts,
+    mut autosave: ResMut<Autosave>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<UndoHistory>,
+    mut timeline: ResMut<Timeline>,
+) {
+    let Some((path, modified)) = autosave.offer.clone() else {
+        return;
+    };
+    let age = modified.elapsed().unwrap_or_default();
+
+    egui::Window::new("Restore autosave")
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "The last session didn't exit cleanly. Its board was autosaved {} ago.",
+                describe_age(age)
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    match Board::load_from_file(&path) {
+                        Ok(loaded) => {
+                            board.replace_tiles(loaded.tiles);
+                            history.clear();
+                            timeline.clear();
+                            autosave.offer = None;
+                        }
+                        Err(err) => autosave.error = Some(err),
+                    }
+                }
+                if ui.button("Start fresh").clicked() {
+                    autosave.offer = None;
+                }
+            });
+            if let Some(error) = autosave.error.as_ref() {
+                ui.colored_label(egui::Color32::LIGHT_RED, error);
+            }
+        });
+}
+
+fn clean_exit(mut exits: EventReader<AppExit>, autosave: Res<Autosave>) {
+    if exits.read().next().is_some() {
+        let _ = fs::remove_file(autosave.dir.join(LOCK_FILE));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "particles-autosave-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a slot as if it was saved `seconds` after some fixed time.
+    fn write_slot(dir: &Path, slot: usize, seconds: u64) {
+        let path = slot_path(dir, slot);
+        fs::write(&path, b"board").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn newest_autosave_follows_the_rotation() {
+        let dir = temp_dir("slots");
+        assert!(newest_autosave(&dir).is_none());
+
+        write_slot(&dir, 0, 0);
+        write_slot(&dir, 1, 60);
+        write_slot(&dir, 2, 120);
+        fs::write(dir.join("autosave-x.board"), b"").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+        let (slot, path, _) = newest_autosave(&dir).unwrap();
+        assert_eq!((slot, path), (2, slot_path(&dir, 2)));
+
+        // Gone round to the first slot again, which is now newer than the higher ones.
+        write_slot(&dir, 0, 180);
+        assert_eq!(newest_autosave(&dir).unwrap().0, 0);
+        write_slot(&dir, 1, 240);
+        assert_eq!(newest_autosave(&dir).unwrap().0, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lock_is_stale_once_its_session_is_gone() {
+        let dir = temp_dir("lock");
+        let lock = dir.join(LOCK_FILE);
+        assert!(!stale_lock(&lock));
+
+        fs::write(&lock, b"not a pid").unwrap();
+        assert!(stale_lock(&lock));
+        // Past the largest pid Linux hands out.
+        fs::write(&lock, b"4294967295").unwrap();
+        assert!(stale_lock(&lock));
+
+        if cfg!(target_os = "linux") {
+            fs::write(&lock, std::process::id().to_string()).unwrap();
+            assert!(!stale_lock(&lock));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{
+    board::{tile_sprites, Board},
+    cli::BenchArgs,
+    physics,
+    settings::RenderMode,
+    tile::{Tile, TileType, MATERIALS},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::time::{Duration, Instant};
+
+/// Ends with the size of a new board, where copying the board for every rule shows up.
+const SIZES: &[usize] = &[16, 32, 64, 100];
+
+/// Starting boards to time, all walled in like a new board.
+const FILLS: &[&str] = &["empty", "half_sand", "full_water", "mixed"];
+
+fn filled_board(fill: &str, size: usize) -> Board {
+    let mut board = Board::new(size, size);
+    let mut rng = StdRng::seed_from_u64(0);
+
+    for x in 1..size - 1 {
+        for y in 1..size - 1 {
+            let tile_type = match fill {
+                "half_sand" if y < size / 2 => TileType::Sand,
+                "full_water" => TileType::Water,
+                "mixed" => MATERIALS.choose(&mut rng).unwrap().tile_type,
+                _ => continue,
+            };
+            board.tiles[x][y] = Tile::from_type(tile_type);
+        }
+    }
+
+    board.clear_dirty_tiles();
+    board
+}
+
+/// Times `run` on a fresh copy of `board` each iteration, returning the median and
+/// the fastest.
+fn measure(
+    board: &Board,
+    iterations: u32,
+    mut run: impl FnMut(&mut Board),
+) -> (Duration, Duration) {
+    let mut times: Vec<Duration> = (0..iterations.max(1))
+        .map(|_| {
+            let mut board = board.clone();
+            let start = Instant::now();
+            run(&mut board);
+            start.elapsed()
+        })
+        .collect();
+
+    times.sort();
+    (times[times.len() / 2], times[0])
+}
+
+fn print_row(name: &str, size: usize, (median, min): (Duration, Duration)) {
+    println!(
+        "{:<24} {:>7} {:>12.1} {:>12.1}",
+        name,
+        format!("{}x{}", size, size),
+        median.as_secs_f64() * 1e6,
+        min.as_secs_f64() * 1e6
+    );
+}
+
+/// Runs `particles bench`. Every line has the same columns in the same order, so two
+/// runs can be compared with `diff`.
+pub fn run(args: &BenchArgs) -> Result<(), String> {
+    println!(
+        "{:<24} {:>7} {:>12} {:>12}",
+        "benchmark", "size", "median_us", "min_us"
+    );
+
+    for &size in SIZES {
+        for fill in FILLS {
+            let board = filled_board(fill, size);
+
+            let tick = measure(&board, args.iterations, |board| {
+                physics::step(board, &mut StdRng::seed_from_u64(0));
+            });
+            print_row(&format!("physics/{}", fill), size, tick);
+
+            let sprites = measure(&board, args.iterations, |board| {
+                std::hint::black_box(tile_sprites(board, RenderMode::Normal));
+            });
+            print_row(&format!("render_data/{}", fill), size, sprites);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! The `.board` file format. Everything is little endian, and counts and lengths are
+//! LEB128 varints:
+//!
+//! ```text
+//! magic       b"PBRD"
+//! version     u16
+//! width       varint
+//! height      varint
+//! materials   varint count, then for each: id u8, name length u8, name (UTF-8)
+//! tiles       varint count of runs, then for each: length varint, material id u8
+//! colours     varint count, then for each: tiles skipped varint, r u8, g u8, b u8
+//! strengths   varint count, then for each: tiles skipped varint, present u8, value u8
+//! ```
+//!
+//! Tiles go column by column from the bottom left, like `Board::tiles`. Colours and
+//! strengths are only stored for tiles whose values differ from their material's
+//! defaults, each one skipping the tiles since the last that did.
+
+use crate::{
+    ascii::tile_type_from_name,
+    board::Board,
+    settings::BOARD_SIZE_RANGE,
+    tile::{Tile, TileType, MATERIALS},
+};
+use bevy::prelude::*;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"PBRD";
+
+/// Bump this when the layout changes, and keep reading the older versions.
+pub const BINARY_VERSION: u16 = 1;
+
+/// Whether a file should be read and written in this format.
+pub fn is_binary_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "board")
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    let [r, g, b, _] = color.as_rgba_u8();
+    [r, g, b]
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Writes `values` as `(tiles skipped, value)` pairs, leaving out the `None`s.
+fn write_sparse<T>(
+    bytes: &mut Vec<u8>,
+    values: impl Iterator<Item = Option<T>>,
+    mut write: impl FnMut(&mut Vec<u8>, T),
+) {
+    let mut entries = Vec::new();
+    let mut count = 0;
+    let mut skipped = 0;
+    for value in values {
+        match value {
+            Some(value) => {
+                write_varint(&mut entries, skipped);
+                write(&mut entries, value);
+                count += 1;
+                skipped = 0;
+            }
+            None => skipped += 1,
+        }
+    }
+    write_varint(bytes, count);
+    bytes.extend(entries);
+}
+
+impl Board {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        write_varint(&mut bytes, self.width() as u64);
+        write_varint(&mut bytes, self.height() as u64);
+
+        write_varint(&mut bytes, MATERIALS.len() as u64);
+        for (id, mat) in MATERIALS.iter().enumerate() {
+            let name = format!("{:?}", mat.tile_type);
+            bytes.push(id as u8);
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        let id = |tile_type: TileType| {
+            MATERIALS
+                .iter()
+                .position(|mat| mat.tile_type == tile_type)
+                .unwrap() as u8
+        };
+
+        let tiles = || self.tiles.iter().flatten();
+
+        let mut runs: Vec<(u64, TileType)> = Vec::new();
+        for tile in tiles() {
+            match runs.last_mut() {
+                Some((length, tile_type)) if *tile_type == tile.tile_type => *length += 1,
+                _ => runs.push((1, tile.tile_type)),
+            }
+        }
+        write_varint(&mut bytes, runs.len() as u64);
+        for (length, tile_type) in runs {
+            write_varint(&mut bytes, length);
+            bytes.push(id(tile_type));
+        }
+
+        let colors = tiles().map(|tile| {
+            let color = rgb(tile.color);
+            (color != rgb(tile.tile_type.base_color())).then_some(color)
+        });
+        write_sparse(&mut bytes, colors, |bytes, color| bytes.extend(color));
+
+        let strengths = tiles().map(|tile| {
+            (tile.strength != Tile::from_type(tile.tile_type).strength).then_some(tile.strength)
+        });
+        write_sparse(&mut bytes, strengths, |bytes, strength| {
+            bytes.extend([strength.is_some() as u8, strength.unwrap_or(0)])
+        });
+
+        bytes
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a binary board".into());
+        }
+        let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+        if version == 0 || version > BINARY_VERSION {
+            return Err(format!(
+                "binary board version {}, this build reads up to {}",
+                version, BINARY_VERSION
+            ));
+        }
+
+        // Checked before anything is allocated, so a bad header can't make it allocate a huge board.
+        let width = reader.length()?;
+        let height = reader.length()?;
+        let max = *BOARD_SIZE_RANGE.end();
+        if width == 0 || height == 0 || width > max || height > max {
+            return Err(format!(
+                "a {}x{} board is empty or more than {} tiles across",
+                width, height, max
+            ));
+        }
+        let size = width * height;
+
+        let mut materials = [None; 256];
+        for _ in 0..reader.length()? {
+            let id = reader.byte()?;
+            let length = reader.byte()? as usize;
+            let name = String::from_utf8_lossy(reader.take(length)?).into_owned();
+            let tile_type =
+                tile_type_from_name(&name).ok_or(format!("unknown material '{}'", name))?;
+            materials[id as usize] = Some(tile_type);
+        }
+
+        let mut tiles: Vec<Tile> = Vec::new();
+        for _ in 0..reader.length()? {
+            let length = reader.length()?;
+            let id = reader.byte()?;
+            let tile_type =
+                materials[id as usize].ok_or(format!("material id {} isn't in the table", id))?;
+            if length > size - tiles.len() {
+                return Err("more tiles than the board has room for".into());
+            }
+            // Tiles without a colour of their own are their material's base colour.
+            let tile = Tile {
+                color: tile_type.base_color(),
+                ..Tile::from_type(tile_type)
+            };
+            tiles.extend(std::iter::repeat_n(tile, length));
+        }
+        if tiles.len() != size {
+            return Err(format!(
+                "{} tiles for a {}x{} board",
+                tiles.len(),
+                width,
+                height
+            ));
+        }
+
+        let mut index = 0usize;
+        for _ in 0..reader.length()? {
+            index = index.saturating_add(reader.length()?);
+            let [r, g, b] = [reader.byte()?, reader.byte()?, reader.byte()?];
+            let tile = tiles.get_mut(index).ok_or("colour past the last tile")?;
+            let alpha = tile.tile_type.base_color().a();
+            tile.color = Color::rgba_u8(r, g, b, 0).with_a(alpha);
+            index += 1;
+        }
+
+        let mut index = 0usize;
+        for _ in 0..reader.length()? {
+            index = index.saturating_add(reader.length()?);
+            let (present, value) = (reader.byte()?, reader.byte()?);
+            let tile = tiles.get_mut(index).ok_or("strength past the last tile")?;
+            tile.strength = (present != 0).then_some(value);
+            index += 1;
+        }
+
+        if reader.position != bytes.len() {
+            return Err("there's more after the board".into());
+        }
+
+        Ok(Board {
+            tiles: tiles.chunks(height).map(<[Tile]>::to_vec).collect(),
+            dirty_tiles: Vec::new(),
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.saturating_add(length))
+            .ok_or("the file ends too soon")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("a number is too long".into())
+    }
+
+    fn length(&mut self) -> Result<usize, String> {
+        usize::try_from(self.varint()?).map_err(|_| "a number is too big".into())
+    }
+}
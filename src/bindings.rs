@@ -0,0 +1,628 @@
+use crate::{
+    consts::BINDINGS_PATH,
+    controls::ui_visible,
+    settings::{write_atomic, Settings, SettingsPath},
+};
+use bevy::{input::InputSystem, prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts, EguiSet};
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                PreUpdate,
+                capture_keys.after(InputSystem).after(EguiSet::InitContexts),
+            )
+            .add_systems(Update, bindings_window.run_if(ui_visible));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    Material1,
+    Material2,
+    Material3,
+    Material4,
+    Material5,
+    Material6,
+    Material7,
+    Material8,
+    Material9,
+    BrushBigger,
+    BrushSmaller,
+    Pause,
+    Step,
+    Undo,
+    Save,
+    ToggleUi,
+    Screenshot,
+    RecordGif,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ZoomIn,
+    ZoomOut,
+    FitBoard,
+    Copy,
+    Paste,
+    Rotate,
+    FlipHorizontal,
+    FlipVertical,
+    Cancel,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Material1,
+        Action::Material2,
+        Action::Material3,
+        Action::Material4,
+        Action::Material5,
+        Action::Material6,
+        Action::Material7,
+        Action::Material8,
+        Action::Material9,
+        Action::BrushBigger,
+        Action::BrushSmaller,
+        Action::Pause,
+        Action::Step,
+        Action::Undo,
+        Action::Save,
+        Action::ToggleUi,
+        Action::Screenshot,
+        Action::RecordGif,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::FitBoard,
+        Action::Copy,
+        Action::Paste,
+        Action::Rotate,
+        Action::FlipHorizontal,
+        Action::FlipVertical,
+        Action::Cancel,
+    ];
+
+    pub const MATERIALS: [Action; 9] = [
+        Action::Material1,
+        Action::Material2,
+        Action::Material3,
+        Action::Material4,
+        Action::Material5,
+        Action::Material6,
+        Action::Material7,
+        Action::Material8,
+        Action::Material9,
+    ];
+
+    pub fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .find(|action| action.name() == name)
+            .copied()
+    }
+
+    fn default_bindings(&self) -> Vec<Binding> {
+        let key = |code| vec![Binding::key(code)];
+        let ctrl = |code| vec![Binding::ctrl(code)];
+
+        match self {
+            Action::Material1 => key(KeyCode::Digit1),
+            Action::Material2 => key(KeyCode::Digit2),
+            Action::Material3 => key(KeyCode::Digit3),
+            Action::Material4 => key(KeyCode::Digit4),
+            Action::Material5 => key(KeyCode::Digit5),
+            Action::Material6 => key(KeyCode::Digit6),
+            Action::Material7 => key(KeyCode::Digit7),
+            Action::Material8 => key(KeyCode::Digit8),
+            Action::Material9 => key(KeyCode::Digit9),
+            Action::BrushBigger => key(KeyCode::BracketRight),
+            Action::BrushSmaller => key(KeyCode::BracketLeft),
+            Action::Pause => key(KeyCode::Space),
+            Action::Step => key(KeyCode::Period),
+            Action::Undo => ctrl(KeyCode::KeyZ),
+            Action::Save => ctrl(KeyCode::KeyS),
+            Action::ToggleUi => key(KeyCode::Tab),
+            Action::Screenshot => key(KeyCode::F12),
+            Action::RecordGif => key(KeyCode::F9),
+            Action::PanUp => key(KeyCode::KeyW),
+            Action::PanDown => key(KeyCode::KeyS),
+            Action::PanLeft => key(KeyCode::KeyA),
+            Action::PanRight => key(KeyCode::KeyD),
+            Action::ZoomIn => key(KeyCode::Equal),
+            Action::ZoomOut => key(KeyCode::Minus),
+            Action::FitBoard => key(KeyCode::KeyF),
+            Action::Copy => ctrl(KeyCode::KeyC),
+            Action::Paste => ctrl(KeyCode::KeyV),
+            Action::Rotate => key(KeyCode::KeyR),
+            Action::FlipHorizontal => key(KeyCode::KeyX),
+            Action::FlipVertical => key(KeyCode::KeyY),
+            Action::Cancel => key(KeyCode::Escape),
+        }
+    }
+}
+
+/// A key plus the modifiers that have to be held with it, written like `Ctrl+KeyZ`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Binding {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    pub fn ctrl(key: KeyCode) -> Self {
+        Binding {
+            ctrl: true,
+            ..Binding::key(key)
+        }
+    }
+
+    fn modifiers_match(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        self.ctrl == keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+            && self.shift == keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+            && self.alt == keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight])
+    }
+
+    pub fn pressed(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        keys.pressed(self.key) && self.modifiers_match(keys)
+    }
+
+    pub fn just_pressed(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        keys.just_pressed(self.key) && self.modifiers_match(keys)
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", key_name(self.key).unwrap_or("?"))
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key_part = parts.pop().unwrap_or_default();
+        let key = key_from_name(key_part).ok_or(format!("Unknown key '{}'.", key_part))?;
+
+        let mut binding = Binding::key(key);
+        for modifier in parts {
+            match modifier {
+                "Ctrl" => binding.ctrl = true,
+                "Shift" => binding.shift = true,
+                "Alt" => binding.alt = true,
+                _ => return Err(format!("Unknown modifier '{}'.", modifier)),
+            }
+        }
+
+        Ok(binding)
+    }
+}
+
+macro_rules! keys {
+    ($($key:ident),* $(,)?) => {
+        const KEYS: &[(KeyCode, &str)] = &[$((KeyCode::$key, stringify!($key))),*];
+    };
+}
+
+keys!(
+    Digit0,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    KeyA,
+    KeyB,
+    KeyC,
+    KeyD,
+    KeyE,
+    KeyF,
+    KeyG,
+    KeyH,
+    KeyI,
+    KeyJ,
+    KeyK,
+    KeyL,
+    KeyM,
+    KeyN,
+    KeyO,
+    KeyP,
+    KeyQ,
+    KeyR,
+    KeyS,
+    KeyT,
+    KeyU,
+    KeyV,
+    KeyW,
+    KeyX,
+    KeyY,
+    KeyZ,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Space,
+    Tab,
+    Enter,
+    Escape,
+    Backspace,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    Minus,
+    Equal,
+    BracketLeft,
+    BracketRight,
+    Backslash,
+    Semicolon,
+    Quote,
+    Backquote,
+    Comma,
+    Period,
+    Slash,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadEnter,
+);
+
+fn key_name(key: KeyCode) -> Option<&'static str> {
+    KEYS.iter()
+        .find(|(code, _)| *code == key)
+        .map(|(_, name)| *name)
+}
+
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().find(|(_, n)| *n == name).map(|(code, _)| *code)
+}
+
+fn is_modifier(key: KeyCode) -> bool {
+    matches!(
+        key,
+        KeyCode::ControlLeft
+            | KeyCode::ControlRight
+            | KeyCode::ShiftLeft
+            | KeyCode::ShiftRight
+            | KeyCode::AltLeft
+            | KeyCode::AltRight
+    )
+}
+
+#[derive(Resource)]
+pub struct Bindings {
+    bindings: HashMap<Action, Vec<Binding>>,
+    /// Problems found while loading or editing, shown in the bindings window.
+    pub errors: Vec<String>,
+    rebinding: Option<Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            bindings: Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_bindings()))
+                .collect(),
+            errors: Vec::new(),
+            rebinding: None,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    pub fn pressed(&self, action: Action, keys: &ButtonInput<KeyCode>) -> bool {
+        self.get(action).iter().any(|binding| binding.pressed(keys))
+    }
+
+    pub fn just_pressed(&self, action: Action, keys: &ButtonInput<KeyCode>) -> bool {
+        self.get(action)
+            .iter()
+            .any(|binding| binding.just_pressed(keys))
+    }
+
+    /// Loads the defaults, then applies any overrides from the bindings file.
+    pub fn load_from_file(path: PathBuf) -> Self {
+        let mut bindings = Bindings::default();
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return bindings,
+        };
+
+        let overrides: BTreeMap<String, Vec<String>> = match serde_json::from_str(&contents) {
+            Ok(overrides) => overrides,
+            Err(err) => {
+                bindings
+                    .errors
+                    .push(format!("Couldn't read {}: {}", path.display(), err));
+                return bindings;
+            }
+        };
+
+        for (name, keys) in overrides {
+            let Some(action) = Action::from_name(&name) else {
+                bindings.errors.push(format!("Unknown action '{}'.", name));
+                continue;
+            };
+
+            let mut parsed = Vec::new();
+            for key in keys {
+                match key.parse::<Binding>() {
+                    Ok(binding) => parsed.push(binding),
+                    Err(err) => bindings.errors.push(format!("{}: {}", name, err)),
+                }
+            }
+            bindings.bindings.insert(action, parsed);
+        }
+
+        bindings.errors.extend(bindings.conflicts());
+        bindings
+    }
+
+    pub fn save_to_file(&self, path: PathBuf) -> std::io::Result<()> {
+        let map: BTreeMap<String, Vec<String>> = Action::ALL
+            .iter()
+            .map(|action| {
+                let keys = self.get(*action).iter().map(|b| b.to_string()).collect();
+                (action.name(), keys)
+            })
+            .collect();
+
+        let serialized = serde_json::to_string_pretty(&map)?;
+        write_atomic(&path, serialized.as_bytes())
+    }
+
+    /// Every binding that is used by more than one action.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut users: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for action in Action::ALL {
+            for binding in self.get(*action) {
+                users
+                    .entry(binding.to_string())
+                    .or_default()
+                    .push(action.name());
+            }
+        }
+
+        users
+            .into_iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .map(|(binding, actions)| format!("{} is bound to {}.", binding, actions.join(", ")))
+            .collect()
+    }
+}
+
+fn setup(mut commands: Commands, path: Res<SettingsPath>) {
+    let bindings = Bindings::load_from_file(path.sibling(BINDINGS_PATH));
+    for error in bindings.errors.iter() {
+        println!("Bindings: {}", error);
+    }
+    commands.insert_resource(bindings);
+}
+
+/// Takes the key that finishes a rebind, and every key while egui is typing into a
+/// text field, before any action sees them.
+fn capture_keys(
+    mut contexts: EguiContexts,
+    mut bindings: ResMut<Bindings>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+) {
+    let pressed = keys
+        .get_just_pressed()
+        .find(|key| !is_modifier(**key) && key_name(**key).is_some())
+        .copied();
+    if let Some(action) = bindings.rebinding {
+        if let Some(key) = pressed {
+            let binding = Binding {
+                key,
+                ctrl: keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+                shift: keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+                alt: keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+            };
+            bindings.bindings.insert(action, vec![binding]);
+            bindings.rebinding = None;
+            bindings.errors = bindings.conflicts();
+            keys.reset(key);
+        }
+    }
+
+    if contexts.ctx_mut().wants_keyboard_input() {
+        keys.reset_all();
+    }
+}
+
+fn bindings_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    path: Res<SettingsPath>,
+    mut bindings: ResMut<Bindings>,
+) {
+    egui::Window::new("Bindings")
+        .open(&mut settings.ui.bindings)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(action.name());
+                    if bindings.rebinding == Some(*action) {
+                        ui.label("Press a key...");
+                    } else {
+                        let keys: Vec<String> = bindings
+                            .get(*action)
+                            .iter()
+                            .map(|b| b.to_string())
+                            .collect();
+                        if ui.button(keys.join(", ")).clicked() {
+                            bindings.rebinding = Some(*action);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+
+            for error in bindings.errors.iter() {
+                ui.colored_label(egui::Color32::LIGHT_RED, error);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    bindings
+                        .save_to_file(path.sibling(BINDINGS_PATH))
+                        .unwrap_or_else(|err| {
+                            println!("Failed to save bindings :(");
+                            println!("{:#?}", err);
+                        });
+                }
+                if ui.button("Reset to defaults").clicked() {
+                    *bindings = Bindings::default();
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_parse_and_display() {
+        let binding: Binding = "Ctrl+Shift+KeyZ".parse().unwrap();
+        assert_eq!(
+            binding,
+            Binding {
+                shift: true,
+                ..Binding::ctrl(KeyCode::KeyZ)
+            }
+        );
+        assert_eq!(binding.to_string(), "Ctrl+Shift+KeyZ");
+
+        for text in ["F12", "Alt+Numpad5", "Ctrl+Shift+Alt+Slash"] {
+            assert_eq!(text.parse::<Binding>().unwrap().to_string(), text);
+        }
+        // Modifiers can come in any order, but display in one.
+        assert_eq!(
+            "Shift+Ctrl+KeyZ".parse::<Binding>().unwrap().to_string(),
+            "Ctrl+Shift+KeyZ"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert_eq!(
+            "Ctrl+Banana".parse::<Binding>(),
+            Err("Unknown key 'Banana'.".into())
+        );
+        assert_eq!("".parse::<Binding>(), Err("Unknown key ''.".into()));
+        assert_eq!(
+            "Hyper+KeyA".parse::<Binding>(),
+            Err("Unknown modifier 'Hyper'.".into())
+        );
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        let mut bindings = Bindings::default();
+        assert!(bindings.conflicts().is_empty());
+
+        bindings
+            .bindings
+            .insert(Action::Rotate, vec![Binding::ctrl(KeyCode::KeyZ)]);
+        assert_eq!(
+            bindings.conflicts(),
+            ["Ctrl+KeyZ is bound to Undo, Rotate."]
+        );
+    }
+
+    #[test]
+    fn missing_actions_keep_their_defaults() {
+        let dir = std::env::temp_dir().join(format!("particles-bindings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(BINDINGS_PATH);
+        std::fs::write(
+            &path,
+            r#"{"Pause": ["KeyP", "Ctrl+Bogus"], "Dance": ["KeyQ"]}"#,
+        )
+        .unwrap();
+
+        let bindings = Bindings::load_from_file(path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(bindings.get(Action::Pause), [Binding::key(KeyCode::KeyP)]);
+        for action in Action::ALL
+            .iter()
+            .filter(|action| **action != Action::Pause)
+        {
+            assert_eq!(bindings.get(*action), action.default_bindings());
+        }
+        assert_eq!(
+            bindings.errors,
+            ["Unknown action 'Dance'.", "Pause: Unknown key 'Bogus'."]
+        );
+    }
+}
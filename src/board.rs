@@ -1,18 +1,34 @@
 use crate::{
-    consts::{HEIGHT, TILE_HEIGHT, TILE_SIZE, TILE_WIDTH, WIDTH},
+    binary::is_binary_path,
+    consts::TILE_SIZE,
+    import::{self, is_image_path},
+    settings::{RenderMode, Settings},
     tile::{Tile, TileType},
 };
 use bevy::{prelude::*, utils::HashSet};
+use std::{fs::File, io::Write, path::Path};
 
 pub struct BoardPlugin;
 
 impl Plugin for BoardPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, init_board)
-            .add_systems(Update, render_board);
+        app.add_systems(Startup, init_board);
+
+        // After everything that edits the board, so the dirty tiles are complete.
+        // Headless runs have nothing to draw to, but still need them cleared.
+        if app.is_plugin_added::<WindowPlugin>() {
+            app.add_systems(PostUpdate, render_board.in_set(DrawBoard));
+        } else {
+            app.add_systems(PostUpdate, clear_dirty_tiles.in_set(DrawBoard));
+        }
     }
 }
 
+/// Where the board is drawn and its dirty tiles cleared. Anything reading the dirty
+/// tiles runs before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DrawBoard;
+
 #[derive(Resource, Default, Reflect, Debug, Clone)]
 pub struct Board {
     pub tiles: Vec<Vec<Tile>>,
@@ -20,8 +36,8 @@ pub struct Board {
 }
 
 impl Board {
-    pub fn new(height: usize, width: usize) -> Self {
-        let tiles = vec![vec![Tile::from_type(TileType::None); width]; height];
+    pub fn new(width: usize, height: usize) -> Self {
+        let tiles = vec![vec![Tile::from_type(TileType::None); height]; width];
 
         let mut board = Board {
             tiles,
@@ -46,10 +62,22 @@ impl Board {
         self.tiles.len()
     }
 
-    pub fn swap(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
-        let temp = self.get(x1, y1).unwrap();
-        self.set(x1, y1, self.get(x2, y2).unwrap()).unwrap();
-        self.set(x2, y2, temp).unwrap();
+    pub fn swap(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) -> Result<(), String> {
+        let out_of_bounds = |x, y| format!("Index ({}, {}) out of bounds.", x, y);
+        let first = self.get(x1, y1).ok_or_else(|| out_of_bounds(x1, y1))?;
+        let second = self.get(x2, y2).ok_or_else(|| out_of_bounds(x2, y2))?;
+        self.set(x1, y1, second)?;
+        self.set(x2, y2, first)
+    }
+
+    /// Swaps in a whole new set of tiles, like a new board or an undone one, possibly
+    /// of a different size. Every tile is marked dirty, so whatever follows the board's
+    /// changes, like the multiplayer server, sees all of it change.
+    pub fn replace_tiles(&mut self, tiles: Vec<Vec<Tile>>) {
+        self.tiles = tiles;
+        self.dirty_tiles = (0..self.width())
+            .flat_map(|x| (0..self.height()).map(move |y| (x, y)))
+            .collect();
     }
 
     pub fn get(&self, x: usize, y: usize) -> Option<Tile> {
@@ -77,7 +105,7 @@ impl Board {
         tile: Tile,
         radius: isize,
     ) -> Result<(), String> {
-        self.set(x, y, tile);
+        self.set(x, y, tile)?;
 
         let center_x = x as i32;
         let center_y = y as i32;
@@ -96,7 +124,7 @@ impl Board {
 
                     // if let Some(tile) = self.get(abs_x as usize, abs_y as usize) {
                     //     if tile.tile_type == TileType::None {
-                    self.set(abs_x as usize, abs_y as usize, tile);
+                    let _ = self.set(abs_x as usize, abs_y as usize, tile);
                     //     }
                     // }
                 }
@@ -106,86 +134,148 @@ impl Board {
         Ok(())
     }
 
+    /// Saves the tile types as JSON, indexed `[x][y]`, as text for `.txt` files, or with
+    /// colours and strengths too for `.board` files.
+    pub fn save_to_file(&self, path: &Path) -> std::io::Result<()> {
+        if is_binary_path(path) {
+            return File::create(path)?.write_all(&self.to_binary());
+        }
+        if is_ascii_path(path) {
+            return File::create(path)?.write_all(self.to_ascii().as_bytes());
+        }
+
+        let serialized = serde_json::to_string(&self.tile_types())?;
+        File::create(path)?.write_all(serialized.as_bytes())?;
+        Ok(())
+    }
+
+    /// Loads a board saved by `save_to_file`, or drawn as a PNG or PPM.
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        if is_image_path(path) {
+            return import::load_board(path);
+        }
+
+        if is_binary_path(path) {
+            let bytes = std::fs::read(path)
+                .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+            return Board::from_binary(&bytes)
+                .map_err(|err| format!("{} isn't a board file: {}", path.display(), err));
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+
+        if is_ascii_path(path) {
+            return Board::from_ascii(&contents)
+                .map_err(|err| format!("{} isn't a board file: {}", path.display(), err));
+        }
+
+        let tile_types: Vec<Vec<TileType>> = serde_json::from_str(&contents)
+            .map_err(|err| format!("{} isn't a board file: {}", path.display(), err))?;
+
+        let height = tile_types.first().map_or(0, |column| column.len());
+        if height == 0 || tile_types.iter().any(|column| column.len() != height) {
+            return Err(format!("{} isn't a rectangular board.", path.display()));
+        }
+
+        Ok(Board::from_tile_types(tile_types))
+    }
+
+    /// The type of every tile, indexed `[x][y]` like `tiles`.
+    pub fn tile_types(&self) -> Vec<Vec<TileType>> {
+        self.tiles
+            .iter()
+            .map(|column| column.iter().map(|tile| tile.tile_type).collect())
+            .collect()
+    }
+
+    /// A board with fresh tiles of the given types, indexed `[x][y]`.
+    pub fn from_tile_types(tile_types: Vec<Vec<TileType>>) -> Self {
+        Board {
+            tiles: tile_types
+                .into_iter()
+                .map(|column| column.into_iter().map(Tile::from_type).collect())
+                .collect(),
+            dirty_tiles: Vec::new(),
+        }
+    }
+
     fn is_in_bounds(&self, x: usize, y: usize) -> bool {
         x < self.tiles.len() && y < self.tiles.first().unwrap().len()
     }
 
-    fn clear_dirty_tiles(&mut self) {
+    pub fn clear_dirty_tiles(&mut self) {
         self.dirty_tiles.clear();
     }
 }
 
-fn init_board(mut commands: Commands) {
-    let resource = Board::new(TILE_HEIGHT, TILE_WIDTH);
+fn is_ascii_path(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "txt")
+}
+
+fn init_board(mut commands: Commands, settings: Res<Settings>, board: Option<Res<Board>>) {
+    // Already loaded from a file.
+    if board.is_some() {
+        return;
+    }
+
+    let resource = Board::new(settings.board.width, settings.board.height);
     commands.insert_resource(resource);
     println!("Board initialized.");
 }
 
-// fn render_board(
-//     mut commands: Commands,
-//     mut board: ResMut<Board>,
-//     mut query: Query<Entity, With<Tile>>,
-// ) {
-//     // Clear existing entities associated with tiles
-//     for entity in query.iter_mut() {
-//         commands.entity(entity).despawn();
-//     }
-//
-//     // Spawn new entities for tiles that have changed
-//     for (x, y) in board
-//         .dirty_tiles
-//         .drain(..)
-//         .collect::<Vec<(usize, usize)>>()
-//         .into_iter()
-//     {
-//         let tile = &board.tiles[x][y];
-//         commands
-//             .spawn(SpriteBundle {
-//                 sprite: Sprite {
-//                     color: tile.color,
-//                     custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
-//                     ..Default::default()
-//                 },
-//                 transform: Transform::from_xyz(
-//                     x as f32 * TILE_SIZE + TILE_SIZE / 2.0,
-//                     y as f32 * TILE_SIZE + TILE_SIZE / 2.0,
-//                     0.0,
-//                 ),
-//                 ..Default::default()
-//             })
-//             .insert(*tile);
-//     }
-// }
+/// Position and colour of every tile that gets drawn, in the given render mode.
+pub fn tile_sprites(board: &Board, render_mode: RenderMode) -> Vec<(usize, usize, Color)> {
+    let dirty: HashSet<(usize, usize)> = board.dirty_tiles.iter().copied().collect();
+    let mut sprites = Vec::new();
+
+    for (x, row) in board.tiles.iter().enumerate() {
+        for (y, tile) in row.iter().enumerate() {
+            if tile.tile_type != TileType::None {
+                let color = match render_mode {
+                    RenderMode::Normal => tile.color,
+                    RenderMode::Flat => tile.tile_type.base_color(),
+                    RenderMode::Dirty if dirty.contains(&(x, y)) => tile.color,
+                    RenderMode::Dirty => tile.color.with_a(0.2),
+                };
+                sprites.push((x, y, color));
+            }
+        }
+    }
+
+    sprites
+}
+
+fn clear_dirty_tiles(mut board: ResMut<Board>) {
+    board.clear_dirty_tiles();
+}
 
 fn render_board(
     mut commands: Commands,
     mut board: ResMut<Board>,
     mut tiles: Query<Entity, With<Tile>>,
+    settings: Res<Settings>,
 ) {
     for entity in tiles.iter_mut() {
         commands.entity(entity).despawn();
     }
 
-    for (x, row) in board.tiles.iter().enumerate() {
-        for (y, tile) in row.iter().enumerate() {
-            if tile.tile_type != TileType::None {
-                commands
-                    .spawn(SpriteBundle {
-                        sprite: Sprite {
-                            color: tile.color,
-                            custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
-                            ..Default::default()
-                        },
-                        transform: Transform::from_xyz(
-                            x as f32 * TILE_SIZE + TILE_SIZE / 2.0,
-                            y as f32 * TILE_SIZE + TILE_SIZE / 2.0,
-                            0.0,
-                        ),
-                        ..Default::default()
-                    })
-                    .insert(*tile);
-            }
-        }
+    for (x, y, color) in tile_sprites(&board, settings.render_mode) {
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(
+                    x as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+                    y as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+                    0.0,
+                ),
+                ..Default::default()
+            })
+            .insert(board.tiles[x][y]);
     }
 
     board.clear_dirty_tiles();
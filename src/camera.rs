@@ -0,0 +1,236 @@
+use crate::{
+    bindings::{Action, Bindings},
+    board::Board,
+    cli::Cli,
+    consts::{HEIGHT, TILE_SIZE, WIDTH},
+};
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::camera::ScalingMode,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+const PAN_SPEED: f32 = 800.;
+const ZOOM_STEP: f32 = 1.1;
+const MIN_SCALE: f32 = 0.05;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostStartup, setup).add_systems(
+            Update,
+            (
+                fit_board,
+                (keyboard_pan, mouse_pan, mouse_zoom, clamp_camera).chain(),
+            ),
+        );
+    }
+}
+
+/// Converts a world position into the board tile it falls on. Tile `(x, y)` covers
+/// the square from `(x, y) * TILE_SIZE` to `(x + 1, y + 1) * TILE_SIZE`.
+pub fn world_to_tile(world_position: Vec2) -> Option<(usize, usize)> {
+    let tile = (world_position / TILE_SIZE).floor();
+    if tile.x < 0. || tile.y < 0. || !tile.is_finite() {
+        return None;
+    }
+
+    Some((tile.x as usize, tile.y as usize))
+}
+
+fn board_size(board: &Board) -> Vec2 {
+    Vec2::new(board.width() as f32, board.height() as f32) * TILE_SIZE
+}
+
+/// Projection scale that fits the whole board in view.
+fn fit_scale(board: &Board) -> f32 {
+    let size = board_size(board);
+    (size.x / WIDTH).max(size.y / HEIGHT) * 1.05
+}
+
+fn setup(
+    mut commands: Commands,
+    board: Res<Board>,
+    cli: Res<Cli>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let mut camera = Camera2dBundle {
+        transform: Transform::from_translation((board_size(&board) / 2.).extend(0.)),
+        ..Default::default()
+    };
+    camera.projection.scaling_mode = ScalingMode::AutoMin {
+        min_height: HEIGHT,
+        min_width: WIDTH,
+    };
+    camera.projection.scale = fit_scale(&board);
+
+    // `--tile-size` sets how many pixels one tile starts out as.
+    if let (Some(tile_size), Ok(window)) = (cli.tile_size, windows.get_single()) {
+        let base_world_per_pixel = (WIDTH / window.width()).max(HEIGHT / window.height());
+        camera.projection.scale = TILE_SIZE / tile_size / base_world_per_pixel;
+    }
+
+    commands.spawn(camera);
+}
+
+fn fit_board(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    board: Res<Board>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection)>,
+) {
+    if !bindings.just_pressed(Action::FitBoard, &keys) {
+        return;
+    }
+
+    let (mut transform, mut projection) = cameras.single_mut();
+    transform.translation = (board_size(&board) / 2.).extend(transform.translation.z);
+    projection.scale = fit_scale(&board);
+}
+
+fn keyboard_pan(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    time: Res<Time>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection)>,
+) {
+    let (mut transform, projection) = cameras.single_mut();
+
+    let mut direction = Vec2::ZERO;
+    if bindings.pressed(Action::PanUp, &keys) {
+        direction.y += 1.;
+    }
+    if bindings.pressed(Action::PanDown, &keys) {
+        direction.y -= 1.;
+    }
+    if bindings.pressed(Action::PanLeft, &keys) {
+        direction.x -= 1.;
+    }
+    if bindings.pressed(Action::PanRight, &keys) {
+        direction.x += 1.;
+    }
+
+    let pan = direction * PAN_SPEED * projection.scale * time.delta_seconds();
+    transform.translation += pan.extend(0.);
+}
+
+fn mouse_pan(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection)>,
+) {
+    let delta: Vec2 = motion.read().map(|event| event.delta).sum();
+    if !mouse.pressed(MouseButton::Middle) || delta == Vec2::ZERO {
+        return;
+    }
+
+    let window = windows.single();
+    let (mut transform, projection) = cameras.single_mut();
+
+    // Screen y points down, world y points up.
+    let world_per_pixel = projection.area.width() / window.width();
+    transform.translation.x -= delta.x * world_per_pixel;
+    transform.translation.y += delta.y * world_per_pixel;
+}
+
+fn mouse_zoom(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    mut wheel: EventReader<MouseWheel>,
+    mut contexts: EguiContexts,
+    board: Res<Board>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(
+        &mut Transform,
+        &mut OrthographicProjection,
+        &Camera,
+        &GlobalTransform,
+    )>,
+) {
+    let mut steps: f32 = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 100.,
+        })
+        .sum();
+    if contexts.ctx_mut().is_pointer_over_area() {
+        steps = 0.;
+    }
+    if bindings.just_pressed(Action::ZoomIn, &keys) {
+        steps += 1.;
+    }
+    if bindings.just_pressed(Action::ZoomOut, &keys) {
+        steps -= 1.;
+    }
+    if steps == 0. {
+        return;
+    }
+
+    let window = windows.single();
+    let (mut transform, mut projection, camera, camera_transform) = cameras.single_mut();
+
+    let old_scale = projection.scale;
+    let new_scale = (old_scale * ZOOM_STEP.powf(-steps)).clamp(MIN_SCALE, fit_scale(&board) * 2.);
+    projection.scale = new_scale;
+
+    // Keep the point under the cursor where it is, or zoom on the centre if there's no cursor.
+    let anchor = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor));
+    if let Some(anchor) = anchor {
+        let centre = transform.translation.truncate();
+        let new_centre = anchor - (anchor - centre) * (new_scale / old_scale);
+        transform.translation = new_centre.extend(transform.translation.z);
+    }
+}
+
+fn clamp_camera(board: Res<Board>, mut cameras: Query<&mut Transform, With<Camera>>) {
+    let size = board_size(&board);
+    let mut transform = cameras.single_mut();
+    transform.translation.x = transform.translation.x.clamp(0., size.x);
+    transform.translation.y = transform.translation.y.clamp(0., size.y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_to_tile_origin() {
+        assert_eq!(world_to_tile(Vec2::ZERO), Some((0, 0)));
+        assert_eq!(world_to_tile(Vec2::splat(TILE_SIZE / 2.)), Some((0, 0)));
+    }
+
+    #[test]
+    fn world_to_tile_edges() {
+        let just_under = TILE_SIZE - 0.001;
+        assert_eq!(
+            world_to_tile(Vec2::new(just_under, just_under)),
+            Some((0, 0))
+        );
+        assert_eq!(world_to_tile(Vec2::new(TILE_SIZE, 0.)), Some((1, 0)));
+        assert_eq!(world_to_tile(Vec2::new(0., TILE_SIZE)), Some((0, 1)));
+        assert_eq!(
+            world_to_tile(Vec2::new(TILE_SIZE * 99.5, TILE_SIZE * 42.)),
+            Some((99, 42))
+        );
+    }
+
+    #[test]
+    fn world_to_tile_negative() {
+        assert_eq!(world_to_tile(Vec2::new(-0.001, 0.)), None);
+        assert_eq!(world_to_tile(Vec2::new(0., -0.001)), None);
+        assert_eq!(world_to_tile(Vec2::new(-TILE_SIZE * 3., TILE_SIZE)), None);
+    }
+
+    #[test]
+    fn world_to_tile_not_finite() {
+        assert_eq!(world_to_tile(Vec2::new(f32::NAN, 0.)), None);
+        assert_eq!(world_to_tile(Vec2::new(0., f32::INFINITY)), None);
+    }
+}
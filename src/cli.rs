@@ -0,0 +1,1020 @@
+use crate::{
+    ascii::tile_type_from_name,
+    export::Region,
+    generate::{WorldParams, DEPTH_RANGE, FRACTION_RANGE},
+    import::DEFAULT_TOLERANCE,
+    settings::{Settings, BOARD_SIZE_RANGE, TICK_RATE_RANGE},
+    tile::TileType,
+};
+use bevy::prelude::*;
+use std::{
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+pub const HELP: &str = "\
+A falling sand sandbox.
+
+Usage: particles [OPTIONS]
+       particles sim [SIM OPTIONS]
+       particles fuzz [FUZZ OPTIONS]
+       particles bench [--iterations <N>]
+       particles replay <FILE> [--out <FILE>]
+       particles import <IMAGE> --out <FILE> [IMPORT OPTIONS]
+       particles generate --out <FILE> [GENERATE OPTIONS]
+
+Commands:
+  sim        Run a board for a number of ticks without a window, see `particles sim --help`
+  fuzz       Step random boards looking for panics and broken invariants
+  bench      Time physics ticks and render data on a set of boards
+  replay     Play a recorded replay without a window and check it ends the same
+  import     Turn a PNG or PPM drawing into a board file, see `particles import --help`
+  generate   Generate terrain from a seed, see `particles generate --help`
+
+Options:
+      --width <TILES>          Board width in tiles
+      --height <TILES>         Board height in tiles
+      --tile-size <PIXELS>     Starting zoom, as the size of one tile on screen
+      --window <WIDTHxHEIGHT>  Window size, e.g. 1280x720
+      --seed <NUMBER>          Seed for the simulation's random numbers
+      --tick-rate <HZ>         Physics ticks per second
+      --load <FILE>            Board file to start with, JSON, .txt, .board or a .png/.ppm image
+      --config <FILE>          Settings file to use instead of the default
+      --headless               Run the simulation without a window
+      --host <ADDRESS>         Share the board with clients connecting to ADDRESS, e.g. 0.0.0.0:7777
+      --connect <ADDRESS>      Join a board hosted with --host
+      --control <ADDRESS>      Take JSON-lines commands on a localhost TCP address or a Unix socket path
+      --frames <DIR>           Save ticks to DIR as numbered PNG frames
+      --gif <FILE>             Record the simulation to an animated GIF
+      --region <X,Y,WxH>       Only record this part of the board in the GIF, from its bottom left
+      --frame-every <N>        Only save every Nth tick with --frames or --gif [default: 1]
+      --scale <N>              Pixels per tile in the frames and GIF [default: 1]
+  -h, --help                   Print this help
+";
+
+pub const SIM_HELP: &str = "\
+Runs a board for a number of ticks as fast as possible and writes the result.
+
+Usage: particles sim --board <FILE> --ticks <N> --out <FILE> [OPTIONS]
+
+Options:
+      --board <FILE>       Board file to start from
+      --ticks <N>          Number of ticks to run
+      --out <FILE>         Where to write the final board
+      --seed <NUMBER>      Seed for the simulation's random numbers [default: 0]
+      --stats <FILE>       Write per-tick statistics as CSV
+      --check              Stop with an error on the first broken physics invariant
+      --png <FILE>         Also write the final board as a PNG
+      --frames <DIR>       Save ticks to DIR as numbered PNG frames, starting with the first board
+      --gif <FILE>         Record the ticks to an animated GIF, starting with the first board
+      --region <X,Y,WxH>   Only record this part of the board in the GIF, from its bottom left
+      --frame-every <N>    Only save every Nth tick with --frames or --gif [default: 1]
+      --scale <N>          Pixels per tile in the PNGs and GIF [default: 1]
+  -h, --help               Print this help
+";
+
+pub const FUZZ_HELP: &str = "\
+Steps random boards of random sizes, without walls, checking the physics invariants.
+Stops at the first board that panics or breaks one and saves it.
+
+Usage: particles fuzz [OPTIONS]
+
+Options:
+      --cases <N>      Number of boards to try [default: 1000]
+      --ticks <N>      Ticks to run each board for [default: 50]
+      --seed <NUMBER>  Seed for generating the boards [default: 0]
+      --out <FILE>     Where to save a failing board [default: fuzz-failure.txt]
+  -h, --help           Print this help
+";
+
+pub const BENCH_HELP: &str = "\
+Times one physics tick and building the render data on empty, half sand, full water
+and mixed boards of a few sizes. The output has fixed columns so runs can be diffed.
+
+Usage: particles bench [OPTIONS]
+
+Options:
+      --iterations <N>  Times to run each benchmark [default: 20]
+  -h, --help            Print this help
+";
+
+pub const REPLAY_HELP: &str = "\
+Plays a replay recorded from the Simulation section of the settings window, and checks
+the board ends up exactly as it was when the recording stopped.
+
+Usage: particles replay <FILE> [OPTIONS]
+
+Options:
+      --out <FILE>  Also write the final board
+  -h, --help        Print this help
+";
+
+pub const IMPORT_HELP: &str = "\
+Turns an image into a board. Each pixel becomes one tile of the material whose colour
+is closest, as long as it's within the tolerance. Transparent pixels are empty.
+Colours that don't match anything are listed and nothing is written, unless
+--unmapped says what to fill them with.
+
+Usage: particles import <IMAGE> --out <FILE> [OPTIONS]
+
+Options:
+      --out <FILE>          Where to write the board, JSON, .txt or .board
+      --width <TILES>       Resize the image to this many tiles across
+      --height <TILES>      Resize the image to this many tiles high
+      --mapping <FILE>      JSON object of exact colours to tile types, e.g. {\"#ff0000\": \"Fire\"}
+      --tolerance <NUMBER>  How far in RGB a colour can be from a material's [default: 64]
+      --unmapped <TYPE>     Tile type for colours that don't match, e.g. None
+  -h, --help                Print this help
+";
+
+pub const GENERATE_HELP: &str = "\
+Generates a board of terrain from a seed: rock, dirt and sand, caves, lakes and trees.
+The same seed and options always give the same tiles.
+
+Usage: particles generate --out <FILE> [OPTIONS]
+
+Options:
+      --out <FILE>         Where to write the board
+      --width <TILES>      Board width in tiles [default: 200]
+      --height <TILES>     Board height in tiles [default: 100]
+      --seed <NUMBER>      Seed for the terrain [default: 0]
+      --ground <FRACTION>  Average height of the ground [default: 0.5]
+      --hills <FRACTION>   How far hills rise and valleys sink [default: 0.12]
+      --sand <ROWS>        Rows of sand on top [default: 2]
+      --dirt <ROWS>        Rows of dirt under the sand [default: 5]
+      --bedrock <ROWS>     Rows of rock along the bottom without caves [default: 2]
+      --caves <FRACTION>   How much of the rock is tunnelled through [default: 0.5]
+      --water <FRACTION>   How high lakes fill the valleys [default: 0.3]
+      --trees <FRACTION>   Chance of a tree on each column of dry ground [default: 0.06]
+  -h, --help               Print this help
+";
+
+/// Options for `particles sim`.
+#[derive(Clone, Debug)]
+pub struct SimArgs {
+    pub board: PathBuf,
+    pub ticks: u64,
+    pub out: PathBuf,
+    pub seed: u64,
+    pub stats: Option<PathBuf>,
+    pub check: bool,
+    pub png: Option<PathBuf>,
+    pub frames: Option<PathBuf>,
+    pub gif: Option<PathBuf>,
+    pub region: Option<Region>,
+    pub frame_every: u64,
+    pub scale: u32,
+}
+
+/// Options for `particles fuzz`.
+#[derive(Clone, Debug)]
+pub struct FuzzArgs {
+    pub cases: u64,
+    pub ticks: u64,
+    pub seed: u64,
+    pub out: PathBuf,
+}
+
+impl Default for FuzzArgs {
+    fn default() -> Self {
+        FuzzArgs {
+            cases: 1000,
+            ticks: 50,
+            seed: 0,
+            out: PathBuf::from("fuzz-failure.txt"),
+        }
+    }
+}
+
+/// Options for `particles bench`.
+#[derive(Clone, Debug)]
+pub struct BenchArgs {
+    pub iterations: u32,
+}
+
+/// Options for `particles replay`.
+#[derive(Clone, Debug)]
+pub struct ReplayArgs {
+    pub replay: PathBuf,
+    pub out: Option<PathBuf>,
+}
+
+/// Options for `particles import`.
+#[derive(Clone, Debug)]
+pub struct ImportArgs {
+    pub image: PathBuf,
+    pub out: PathBuf,
+    /// Board size to resize the image to, if not its own.
+    pub size: Option<(usize, usize)>,
+    pub mapping: Option<PathBuf>,
+    pub tolerance: f32,
+    pub unmapped: Option<TileType>,
+}
+
+/// Options for `particles generate`.
+#[derive(Clone, Debug)]
+pub struct GenerateArgs {
+    pub width: usize,
+    pub height: usize,
+    pub params: WorldParams,
+    pub out: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Sim(SimArgs),
+    Fuzz(FuzzArgs),
+    Bench(BenchArgs),
+    Replay(ReplayArgs),
+    Import(ImportArgs),
+    Generate(GenerateArgs),
+}
+
+/// Options given on the command line. Anything left out falls back to the settings file.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Cli {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub tile_size: Option<f32>,
+    pub window: Option<(f32, f32)>,
+    pub seed: Option<u64>,
+    pub tick_rate: Option<f64>,
+    pub load: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub headless: bool,
+    pub host: Option<String>,
+    pub connect: Option<String>,
+    pub control: Option<String>,
+    pub frames: Option<PathBuf>,
+    pub gif: Option<PathBuf>,
+    pub region: Option<Region>,
+    pub frame_every: Option<u64>,
+    pub scale: Option<u32>,
+    /// Help text to print instead of running.
+    pub help: Option<&'static str>,
+    pub command: Option<Command>,
+}
+
+#[derive(Debug)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError(format!("invalid value '{}' for {}", value, flag)))
+}
+
+fn parse_window(value: &str) -> Result<(f32, f32), CliError> {
+    let error = || {
+        CliError(format!(
+            "invalid window size '{}', expected e.g. 1280x720",
+            value
+        ))
+    };
+    let (width, height) = value.split_once('x').ok_or_else(error)?;
+    let width: f32 = width.parse().map_err(|_| error())?;
+    let height: f32 = height.parse().map_err(|_| error())?;
+    Ok((width, height))
+}
+
+fn parse_region(value: &str) -> Result<Region, CliError> {
+    let error = || {
+        CliError(format!(
+            "invalid region '{}', expected e.g. 10,5,40x30",
+            value
+        ))
+    };
+    let mut parts = value.splitn(3, ',');
+    let (Some(x), Some(y), Some(size)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(error());
+    };
+    let (width, height) = size.split_once('x').ok_or_else(error)?;
+    let region = Region {
+        x: x.parse().map_err(|_| error())?,
+        y: y.parse().map_err(|_| error())?,
+        width: width.parse().map_err(|_| error())?,
+        height: height.parse().map_err(|_| error())?,
+    };
+    if region.width == 0 || region.height == 0 {
+        return Err(CliError("--region must be at least 1x1".into()));
+    }
+    Ok(region)
+}
+
+/// Splits `--flag=value` into its parts.
+fn split_flag(arg: &str) -> (String, Option<String>) {
+    match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with("--") => {
+            (flag.to_string(), Some(value.to_string()))
+        }
+        _ => (arg.to_string(), None),
+    }
+}
+
+fn check_frame_options(frame_every: u64, scale: u32) -> Result<(), CliError> {
+    if frame_every == 0 {
+        return Err(CliError("--frame-every must be at least 1".into()));
+    }
+    if scale == 0 {
+        return Err(CliError("--scale must be at least 1".into()));
+    }
+    Ok(())
+}
+
+/// Parses the arguments after `sim`. Returns `None` if help was asked for.
+fn parse_sim(mut args: impl Iterator<Item = String>) -> Result<Option<SimArgs>, CliError> {
+    let (mut board, mut ticks, mut out, mut seed, mut stats) = (None, None, None, 0, None);
+    let mut check = false;
+    let (mut png, mut frames, mut frame_every, mut scale) = (None, None, 1, 1);
+    let (mut gif, mut region) = (None, None);
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError(format!("{} needs a value", flag)))
+        };
+
+        match flag.as_str() {
+            "--board" => board = Some(PathBuf::from(value()?)),
+            "--ticks" => ticks = Some(parse_value(&flag, &value()?)?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--seed" => seed = parse_value(&flag, &value()?)?,
+            "--stats" => stats = Some(PathBuf::from(value()?)),
+            "--check" => check = true,
+            "--png" => png = Some(PathBuf::from(value()?)),
+            "--frames" => frames = Some(PathBuf::from(value()?)),
+            "--gif" => gif = Some(PathBuf::from(value()?)),
+            "--region" => region = Some(parse_region(&value()?)?),
+            "--frame-every" => frame_every = parse_value(&flag, &value()?)?,
+            "--scale" => scale = parse_value(&flag, &value()?)?,
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    let missing = |flag: &str| CliError(format!("sim needs {}", flag));
+    let board = board.ok_or_else(|| missing("--board"))?;
+    if !board.is_file() {
+        return Err(CliError(format!(
+            "board file {} not found",
+            board.display()
+        )));
+    }
+    check_frame_options(frame_every, scale)?;
+    if region.is_some() && gif.is_none() {
+        return Err(CliError("--region only applies to --gif".into()));
+    }
+
+    Ok(Some(SimArgs {
+        board,
+        ticks: ticks.ok_or_else(|| missing("--ticks"))?,
+        out: out.ok_or_else(|| missing("--out"))?,
+        seed,
+        stats,
+        check,
+        png,
+        frames,
+        gif,
+        region,
+        frame_every,
+        scale,
+    }))
+}
+
+/// Parses the arguments after `fuzz`. Returns `None` if help was asked for.
+fn parse_fuzz(mut args: impl Iterator<Item = String>) -> Result<Option<FuzzArgs>, CliError> {
+    let mut fuzz = FuzzArgs::default();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError(format!("{} needs a value", flag)))
+        };
+
+        match flag.as_str() {
+            "--cases" => fuzz.cases = parse_value(&flag, &value()?)?,
+            "--ticks" => fuzz.ticks = parse_value(&flag, &value()?)?,
+            "--seed" => fuzz.seed = parse_value(&flag, &value()?)?,
+            "--out" => fuzz.out = PathBuf::from(value()?),
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    Ok(Some(fuzz))
+}
+
+/// Parses the arguments after `generate`. Returns `None` if help was asked for.
+fn parse_generate(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<GenerateArgs>, CliError> {
+    let (mut out, mut width, mut height) = (None, 200, 100);
+    let mut params = WorldParams::default();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError(format!("{} needs a value", flag)))
+        };
+
+        match flag.as_str() {
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--width" => width = parse_value(&flag, &value()?)?,
+            "--height" => height = parse_value(&flag, &value()?)?,
+            "--seed" => params.seed = parse_value(&flag, &value()?)?,
+            "--ground" => params.ground = parse_value(&flag, &value()?)?,
+            "--hills" => params.hills = parse_value(&flag, &value()?)?,
+            "--sand" => params.sand_depth = parse_value(&flag, &value()?)?,
+            "--dirt" => params.dirt_depth = parse_value(&flag, &value()?)?,
+            "--bedrock" => params.bedrock = parse_value(&flag, &value()?)?,
+            "--caves" => params.caves = parse_value(&flag, &value()?)?,
+            "--water" => params.water = parse_value(&flag, &value()?)?,
+            "--trees" => params.trees = parse_value(&flag, &value()?)?,
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    let out = out.ok_or(CliError("generate needs --out".into()))?;
+    for (flag, size) in [("--width", width), ("--height", height)] {
+        if !BOARD_SIZE_RANGE.contains(&size) {
+            return Err(CliError(format!(
+                "{} must be between {} and {}",
+                flag,
+                BOARD_SIZE_RANGE.start(),
+                BOARD_SIZE_RANGE.end()
+            )));
+        }
+    }
+    for (flag, value) in [
+        ("--ground", params.ground),
+        ("--hills", params.hills),
+        ("--caves", params.caves),
+        ("--water", params.water),
+        ("--trees", params.trees),
+    ] {
+        if !FRACTION_RANGE.contains(&value) {
+            return Err(CliError(format!("{} must be between 0 and 1", flag)));
+        }
+    }
+    for (flag, rows) in [
+        ("--sand", params.sand_depth),
+        ("--dirt", params.dirt_depth),
+        ("--bedrock", params.bedrock),
+    ] {
+        if !DEPTH_RANGE.contains(&rows) {
+            return Err(CliError(format!(
+                "{} must be at most {}",
+                flag,
+                DEPTH_RANGE.end()
+            )));
+        }
+    }
+
+    Ok(Some(GenerateArgs {
+        width,
+        height,
+        params,
+        out,
+    }))
+}
+
+/// Parses the arguments after `bench`. Returns `None` if help was asked for.
+fn parse_bench(mut args: impl Iterator<Item = String>) -> Result<Option<BenchArgs>, CliError> {
+    let mut bench = BenchArgs { iterations: 20 };
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError(format!("{} needs a value", flag)))
+        };
+
+        match flag.as_str() {
+            "--iterations" => bench.iterations = parse_value(&flag, &value()?)?,
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    Ok(Some(bench))
+}
+
+/// Parses the arguments after `replay`. Returns `None` if help was asked for.
+fn parse_replay(mut args: impl Iterator<Item = String>) -> Result<Option<ReplayArgs>, CliError> {
+    let (mut replay, mut out) = (None, None);
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError(format!("{} needs a value", flag)))
+        };
+
+        match flag.as_str() {
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "-h" | "--help" => return Ok(None),
+            _ if replay.is_none() && !arg.starts_with('-') => replay = Some(PathBuf::from(arg)),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    let replay = replay.ok_or(CliError("replay needs a replay file".into()))?;
+    if !replay.is_file() {
+        return Err(CliError(format!(
+            "replay file {} not found",
+            replay.display()
+        )));
+    }
+
+    Ok(Some(ReplayArgs { replay, out }))
+}
+
+/// Parses the arguments after `import`. Returns `None` if help was asked for.
+fn parse_import(mut args: impl Iterator<Item = String>) -> Result<Option<ImportArgs>, CliError> {
+    let (mut image, mut out, mut width, mut height) = (None, None, None, None);
+    let (mut mapping, mut tolerance, mut unmapped) = (None, DEFAULT_TOLERANCE, None);
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError(format!("{} needs a value", flag)))
+        };
+
+        match flag.as_str() {
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--width" => width = Some(parse_value(&flag, &value()?)?),
+            "--height" => height = Some(parse_value(&flag, &value()?)?),
+            "--mapping" => mapping = Some(PathBuf::from(value()?)),
+            "--tolerance" => tolerance = parse_value(&flag, &value()?)?,
+            "--unmapped" => {
+                let name = value()?;
+                unmapped = Some(
+                    tile_type_from_name(&name)
+                        .ok_or(CliError(format!("unknown tile type '{}'", name)))?,
+                );
+            }
+            "-h" | "--help" => return Ok(None),
+            _ if image.is_none() && !arg.starts_with('-') => image = Some(PathBuf::from(arg)),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    let image = image.ok_or(CliError("import needs an image".into()))?;
+    if !image.is_file() {
+        return Err(CliError(format!("image {} not found", image.display())));
+    }
+    let out = out.ok_or(CliError("import needs --out".into()))?;
+
+    let size = match (width, height) {
+        (None, None) => None,
+        (Some(width), Some(height)) => Some((width, height)),
+        _ => {
+            return Err(CliError(
+                "--width and --height have to be given together".into(),
+            ))
+        }
+    };
+    if size.is_some_and(|(width, height)| {
+        !BOARD_SIZE_RANGE.contains(&width) || !BOARD_SIZE_RANGE.contains(&height)
+    }) {
+        return Err(CliError(format!(
+            "--width and --height must be between {} and {}",
+            BOARD_SIZE_RANGE.start(),
+            BOARD_SIZE_RANGE.end()
+        )));
+    }
+    if tolerance.is_nan() || tolerance < 0. {
+        return Err(CliError("--tolerance can't be negative".into()));
+    }
+
+    Ok(Some(ImportArgs {
+        image,
+        out,
+        size,
+        mapping,
+        tolerance,
+        unmapped,
+    }))
+}
+
+impl Cli {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut cli = Cli::default();
+        let mut args = args.into_iter().peekable();
+
+        match args.peek().map(String::as_str) {
+            Some("sim") => {
+                args.next();
+                match parse_sim(args)? {
+                    Some(sim) => cli.command = Some(Command::Sim(sim)),
+                    None => cli.help = Some(SIM_HELP),
+                }
+                return Ok(cli);
+            }
+            Some("fuzz") => {
+                args.next();
+                match parse_fuzz(args)? {
+                    Some(fuzz) => cli.command = Some(Command::Fuzz(fuzz)),
+                    None => cli.help = Some(FUZZ_HELP),
+                }
+                return Ok(cli);
+            }
+            Some("bench") => {
+                args.next();
+                match parse_bench(args)? {
+                    Some(bench) => cli.command = Some(Command::Bench(bench)),
+                    None => cli.help = Some(BENCH_HELP),
+                }
+                return Ok(cli);
+            }
+            Some("replay") => {
+                args.next();
+                match parse_replay(args)? {
+                    Some(replay) => cli.command = Some(Command::Replay(replay)),
+                    None => cli.help = Some(REPLAY_HELP),
+                }
+                return Ok(cli);
+            }
+            Some("import") => {
+                args.next();
+                match parse_import(args)? {
+                    Some(import) => cli.command = Some(Command::Import(import)),
+                    None => cli.help = Some(IMPORT_HELP),
+                }
+                return Ok(cli);
+            }
+            Some("generate") => {
+                args.next();
+                match parse_generate(args)? {
+                    Some(generate) => cli.command = Some(Command::Generate(generate)),
+                    None => cli.help = Some(GENERATE_HELP),
+                }
+                return Ok(cli);
+            }
+            _ => {}
+        }
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = split_flag(&arg);
+
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or(CliError(format!("{} needs a value", flag)))
+            };
+
+            match flag.as_str() {
+                "--width" => cli.width = Some(parse_value(&flag, &value()?)?),
+                "--height" => cli.height = Some(parse_value(&flag, &value()?)?),
+                "--tile-size" => cli.tile_size = Some(parse_value(&flag, &value()?)?),
+                "--window" => cli.window = Some(parse_window(&value()?)?),
+                "--seed" => cli.seed = Some(parse_value(&flag, &value()?)?),
+                "--tick-rate" => cli.tick_rate = Some(parse_value(&flag, &value()?)?),
+                "--load" => cli.load = Some(PathBuf::from(value()?)),
+                "--config" => cli.config = Some(PathBuf::from(value()?)),
+                "--headless" => cli.headless = true,
+                "--host" => cli.host = Some(value()?),
+                "--connect" => cli.connect = Some(value()?),
+                "--control" => cli.control = Some(value()?),
+                "--frames" => cli.frames = Some(PathBuf::from(value()?)),
+                "--gif" => cli.gif = Some(PathBuf::from(value()?)),
+                "--region" => cli.region = Some(parse_region(&value()?)?),
+                "--frame-every" => cli.frame_every = Some(parse_value(&flag, &value()?)?),
+                "--scale" => cli.scale = Some(parse_value(&flag, &value()?)?),
+                "-h" | "--help" => cli.help = Some(HELP),
+                _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+            }
+        }
+
+        Ok(cli)
+    }
+
+    /// Checks values and combinations that can be caught before anything starts.
+    pub fn validate(&self) -> Result<(), CliError> {
+        for (flag, size) in [("--width", self.width), ("--height", self.height)] {
+            if let Some(size) = size {
+                if !BOARD_SIZE_RANGE.contains(&size) {
+                    return Err(CliError(format!(
+                        "{} must be between {} and {}",
+                        flag,
+                        BOARD_SIZE_RANGE.start(),
+                        BOARD_SIZE_RANGE.end()
+                    )));
+                }
+            }
+        }
+
+        // A missing settings file is written once anything is changed, so only its
+        // directory has to exist.
+        if let Some(path) = &self.config {
+            match fs::read_to_string(path) {
+                Ok(contents) => {
+                    let (_, errors) = Settings::from_json(&contents);
+                    if let Some(error) = errors.iter().find(|error| error.field.is_empty()) {
+                        return Err(CliError(format!(
+                            "settings file {} isn't valid: {}",
+                            path.display(),
+                            error
+                        )));
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    let directory = match path.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                    if !directory.is_dir() {
+                        return Err(CliError(format!(
+                            "settings file {}'s directory doesn't exist",
+                            path.display()
+                        )));
+                    }
+                }
+                Err(err) => {
+                    return Err(CliError(format!(
+                        "couldn't read settings file {}: {}",
+                        path.display(),
+                        err
+                    )))
+                }
+            }
+        }
+
+        if let Some(tick_rate) = self.tick_rate {
+            if !TICK_RATE_RANGE.contains(&tick_rate) {
+                return Err(CliError(format!(
+                    "--tick-rate must be between {} and {}",
+                    TICK_RATE_RANGE.start(),
+                    TICK_RATE_RANGE.end()
+                )));
+            }
+        }
+
+        if self
+            .tile_size
+            .is_some_and(|size| !(size > 0. && size.is_finite()))
+        {
+            return Err(CliError("--tile-size must be above 0".into()));
+        }
+
+        if self
+            .window
+            .is_some_and(|(width, height)| !(width >= 1. && height >= 1.))
+        {
+            return Err(CliError("--window must be at least 1x1".into()));
+        }
+
+        if self.headless && (self.window.is_some() || self.tile_size.is_some()) {
+            return Err(CliError(
+                "--window and --tile-size have no effect with --headless".into(),
+            ));
+        }
+
+        if self.connect.is_some() {
+            if self.host.is_some() {
+                return Err(CliError(
+                    "--host and --connect can't be used together".into(),
+                ));
+            }
+            if self.load.is_some() || self.width.is_some() || self.height.is_some() {
+                return Err(CliError(
+                    "--load, --width and --height have no effect with --connect, the server sets the board"
+                        .into(),
+                ));
+            }
+        }
+
+        if let Some(address) = self
+            .control
+            .as_ref()
+            .and_then(|address| address.parse::<SocketAddr>().ok())
+        {
+            if !address.ip().is_loopback() {
+                return Err(CliError(
+                    "--control only listens on localhost, e.g. 127.0.0.1:7878".into(),
+                ));
+            }
+        }
+
+        if self.frames.is_none()
+            && self.gif.is_none()
+            && (self.frame_every.is_some() || self.scale.is_some())
+        {
+            return Err(CliError(
+                "--frame-every and --scale only apply to --frames and --gif".into(),
+            ));
+        }
+        if self.region.is_some() && self.gif.is_none() {
+            return Err(CliError("--region only applies to --gif".into()));
+        }
+        check_frame_options(self.frame_every.unwrap_or(1), self.scale.unwrap_or(1))?;
+
+        if let Some(path) = &self.load {
+            if self.width.is_some() || self.height.is_some() {
+                return Err(CliError(
+                    "--width and --height can't be used with --load, the board file sets the size"
+                        .into(),
+                ));
+            }
+            if !path.is_file() {
+                return Err(CliError(format!("board file {} not found", path.display())));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overrides the loaded settings for this run.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(width) = self.width {
+            settings.board.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.board.height = height;
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            settings.tick_rate = tick_rate;
+        }
+        if self.seed.is_some() {
+            settings.seed = self.seed;
+        }
+    }
+
+    /// Undoes `apply`, putting back the values from `loaded` so they're what gets saved.
+    pub fn unapply(&self, settings: &mut Settings, loaded: &Settings) {
+        if self.width.is_some() {
+            settings.board.width = loaded.board.width;
+        }
+        if self.height.is_some() {
+            settings.board.height = loaded.board.height;
+        }
+        if self.tick_rate.is_some() {
+            settings.tick_rate = loaded.tick_rate;
+        }
+        if self.seed.is_some() {
+            settings.seed = loaded.seed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, CliError> {
+        Cli::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args).and_then(|cli| cli.validate().map(|()| cli)) {
+            Ok(cli) => panic!("{:?} was accepted as {:?}", args, cli),
+            Err(err) => err.0,
+        }
+    }
+
+    #[test]
+    fn flags_take_separate_or_inline_values() {
+        let cli = parse(&[
+            "--width",
+            "200",
+            "--height=150",
+            "--seed",
+            "7",
+            "--headless",
+        ])
+        .unwrap();
+        assert_eq!(cli.width, Some(200));
+        assert_eq!(cli.height, Some(150));
+        assert_eq!(cli.seed, Some(7));
+        assert!(cli.headless);
+        assert!(cli.command.is_none());
+        cli.validate().unwrap();
+    }
+
+    #[test]
+    fn window_and_region_sizes() {
+        let cli = parse(&[
+            "--window",
+            "1280x720",
+            "--gif",
+            "out.gif",
+            "--region=1,2,30x40",
+        ])
+        .unwrap();
+        assert_eq!(cli.window, Some((1280., 720.)));
+        let region = cli.region.unwrap();
+        assert_eq!(
+            (region.x, region.y, region.width, region.height),
+            (1, 2, 30, 40)
+        );
+
+        assert!(error(&["--window", "1280"]).contains("invalid window size"));
+        assert!(error(&["--gif", "a.gif", "--region", "1,2,0x4"]).contains("at least 1x1"));
+    }
+
+    #[test]
+    fn help() {
+        assert_eq!(parse(&["--help"]).unwrap().help, Some(HELP));
+        assert_eq!(parse(&["sim", "-h"]).unwrap().help, Some(SIM_HELP));
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        assert_eq!(error(&["--bogus"]), "unexpected argument '--bogus'");
+        assert_eq!(error(&["--width"]), "--width needs a value");
+        assert_eq!(
+            error(&["--width", "wide"]),
+            "invalid value 'wide' for --width"
+        );
+        assert!(error(&["--width", "1"]).starts_with("--width must be between"));
+        assert!(error(&["--tick-rate", "0"]).starts_with("--tick-rate must be between"));
+        assert!(error(&["--headless", "--window", "10x10"]).contains("--headless"));
+        assert!(error(&["--host", "0.0.0.0:1", "--connect", "a:1"]).contains("together"));
+        assert!(error(&["--control", "0.0.0.0:7878"]).contains("localhost"));
+        assert!(error(&["--scale", "2"]).contains("--frames and --gif"));
+        assert_eq!(error(&["generate", "--seed", "7"]), "generate needs --out");
+        assert_eq!(
+            error(&["generate", "--check"]),
+            "unexpected argument '--check'"
+        );
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let missing = "this/file/does/not/exist.json";
+        assert_eq!(
+            error(&["--config", missing]),
+            format!("settings file {}'s directory doesn't exist", missing)
+        );
+        assert_eq!(
+            error(&["--load", missing]),
+            format!("board file {} not found", missing)
+        );
+        assert!(
+            error(&["sim", "--board", missing, "--ticks", "1", "--out", "o"]).contains("not found")
+        );
+    }
+
+    #[test]
+    fn config_file_may_be_missing_but_not_invalid() {
+        let dir = std::env::temp_dir().join(format!("particles-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("settings.json");
+        let _ = fs::remove_file(&config);
+        let config = config.to_str().unwrap();
+
+        // Created with the defaults once a setting changes.
+        parse(&["--config", config]).unwrap().validate().unwrap();
+        parse(&["--config=settings.json"])
+            .unwrap()
+            .validate()
+            .unwrap();
+
+        fs::write(config, "{ not json").unwrap();
+        assert!(error(&["--config", config]).contains("isn't valid"));
+        assert!(error(&["--config", dir.to_str().unwrap()]).starts_with("couldn't read"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn apply_and_unapply() {
+        let cli = parse(&["--width", "500", "--tick-rate", "30"]).unwrap();
+        let loaded = Settings::default();
+        let mut settings = loaded.clone();
+
+        cli.apply(&mut settings);
+        assert_eq!(settings.board.width, 500);
+        assert_eq!(settings.tick_rate, 30.);
+        assert_eq!(settings.board.height, loaded.board.height);
+
+        cli.unapply(&mut settings, &loaded);
+        assert_eq!(settings, loaded);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::{
+    bindings::{Action, Bindings},
+    board::Board,
+    consts::TILE_SIZE,
+    controls::{cursor_tile, pointer_over_ui, ui_visible, UndoHistory},
+    settings::{PaintMode, Settings},
+    tile::{Tile, TileType},
+};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+pub struct ClipboardPlugin;
+
+impl Plugin for ClipboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Tool>()
+            .init_resource::<Clipboard>()
+            .add_systems(
+                Update,
+                (
+                    clipboard_keys,
+                    select_controls,
+                    paste_controls,
+                    render_ghost,
+                    clipboard_window.run_if(ui_visible),
+                ),
+            );
+    }
+}
+
+/// What the left mouse button currently does.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    #[default]
+    Brush,
+    Select,
+    Paste,
+}
+
+/// A rectangular block of tiles, indexed `tiles[x][y]` like the board.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pattern {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Vec<TileType>>,
+}
+
+impl Pattern {
+    /// Copies the inclusive rectangle between two corners of the board.
+    pub fn from_region(board: &Board, x1: usize, y1: usize, x2: usize, y2: usize) -> Self {
+        let (min_x, max_x) = (x1.min(x2), x1.max(x2).min(board.width() - 1));
+        let (min_y, max_y) = (y1.min(y2), y1.max(y2).min(board.height() - 1));
+
+        let tiles: Vec<Vec<TileType>> = (min_x..=max_x)
+            .map(|x| {
+                (min_y..=max_y)
+                    .map(|y| board.tiles[x][y].tile_type)
+                    .collect()
+            })
+            .collect();
+
+        Pattern {
+            width: tiles.len(),
+            height: tiles.first().map_or(0, |column| column.len()),
+            tiles,
+        }
+    }
+
+    /// Checks `width` and `height` match `tiles`, which patterns read from a file
+    /// needn't.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tiles.len() != self.width {
+            return Err(format!(
+                "width is {} but there are {} columns",
+                self.width,
+                self.tiles.len()
+            ));
+        }
+        if let Some(x) = self
+            .tiles
+            .iter()
+            .position(|column| column.len() != self.height)
+        {
+            return Err(format!(
+                "height is {} but column {} has {} tiles",
+                self.height,
+                x,
+                self.tiles[x].len()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> TileType {
+        self.tiles[x][y]
+    }
+
+    /// Rotates the pattern 90° clockwise.
+    pub fn rotate(&self) -> Self {
+        let mut tiles = vec![vec![TileType::None; self.width]; self.height];
+        for (x, column) in self.tiles.iter().enumerate() {
+            for (y, tile_type) in column.iter().enumerate() {
+                tiles[y][self.width - 1 - x] = *tile_type;
+            }
+        }
+
+        Pattern {
+            width: self.height,
+            height: self.width,
+            tiles,
+        }
+    }
+
+    /// Mirrors the pattern left to right.
+    pub fn flip_horizontal(&self) -> Self {
+        let mut tiles = self.tiles.clone();
+        tiles.reverse();
+
+        Pattern { tiles, ..*self }
+    }
+
+    /// Mirrors the pattern top to bottom.
+    pub fn flip_vertical(&self) -> Self {
+        let mut tiles = self.tiles.clone();
+        for column in tiles.iter_mut() {
+            column.reverse();
+        }
+
+        Pattern { tiles, ..*self }
+    }
+
+    /// Bottom left corner of the pattern when it is centred on `(x, y)`.
+    pub fn origin(&self, x: usize, y: usize) -> (isize, isize) {
+        (
+            x as isize - (self.width / 2) as isize,
+            y as isize - (self.height / 2) as isize,
+        )
+    }
+
+    /// Writes the pattern onto the board centred on `(x, y)`. In `PaintMode::Fill`
+    /// empty pattern tiles are skipped and only empty board tiles are written to.
+    pub fn paste(&self, board: &mut Board, x: usize, y: usize, mode: PaintMode) {
+        let (origin_x, origin_y) = self.origin(x, y);
+
+        for px in 0..self.width {
+            for py in 0..self.height {
+                let (bx, by) = (origin_x + px as isize, origin_y + py as isize);
+                if bx < 0 || by < 0 {
+                    continue;
+                }
+                let (bx, by) = (bx as usize, by as usize);
+
+                let tile_type = self.get(px, py);
+                if mode == PaintMode::Fill {
+                    let target = board.get(bx, by).map(|tile| tile.tile_type);
+                    if tile_type == TileType::None || target != Some(TileType::None) {
+                        continue;
+                    }
+                }
+
+                let _ = board.set(bx, by, Tile::from_type(tile_type));
+            }
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Clipboard {
+    pub pattern: Option<Pattern>,
+    /// Corners of the current selection, in tile coordinates.
+    pub selection: Option<((usize, usize), (usize, usize))>,
+    dragging: bool,
+}
+
+impl Clipboard {
+    pub fn copy(&mut self, board: &Board) {
+        if let Some(((x1, y1), (x2, y2))) = self.selection {
+            self.pattern = Some(Pattern::from_region(board, x1, y1, x2, y2));
+        }
+    }
+
+    fn transform(&mut self, f: impl Fn(&Pattern) -> Pattern) {
+        if let Some(pattern) = &self.pattern {
+            self.pattern = Some(f(pattern));
+        }
+    }
+}
+
+#[derive(Component)]
+struct Ghost;
+
+fn clipboard_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    board: Res<Board>,
+    mut clipboard: ResMut<Clipboard>,
+    mut tool: ResMut<Tool>,
+) {
+    if bindings.just_pressed(Action::Copy, &keys) {
+        clipboard.copy(&board);
+    }
+    if bindings.just_pressed(Action::Paste, &keys) && clipboard.pattern.is_some() {
+        *tool = Tool::Paste;
+    }
+    if bindings.just_pressed(Action::Rotate, &keys) {
+        clipboard.transform(Pattern::rotate);
+    }
+    if bindings.just_pressed(Action::FlipHorizontal, &keys) {
+        clipboard.transform(Pattern::flip_horizontal);
+    }
+    if bindings.just_pressed(Action::FlipVertical, &keys) {
+        clipboard.transform(Pattern::flip_vertical);
+    }
+    if bindings.just_pressed(Action::Cancel, &keys) {
+        *tool = Tool::Brush;
+        clipboard.selection = None;
+    }
+}
+
+fn select_controls(
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    tool: Res<Tool>,
+    mut clipboard: ResMut<Clipboard>,
+) {
+    if *tool != Tool::Select || pointer_over_ui(&mut contexts) {
+        return;
+    }
+
+    let window = windows.single();
+    let (camera, camera_transform) = cameras.single();
+    let Some(tile) = cursor_tile(window, camera, camera_transform) else {
+        return;
+    };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        clipboard.selection = Some((tile, tile));
+        clipboard.dragging = true;
+    } else if mouse.pressed(MouseButton::Left) && clipboard.dragging {
+        if let Some((start, _)) = clipboard.selection {
+            clipboard.selection = Some((start, tile));
+        }
+    } else {
+        clipboard.dragging = false;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn paste_controls(
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    tool: Res<Tool>,
+    clipboard: Res<Clipboard>,
+    settings: Res<Settings>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<UndoHistory>,
+) {
+    if *tool != Tool::Paste
+        || !mouse.just_pressed(MouseButton::Left)
+        || pointer_over_ui(&mut contexts)
+    {
+        return;
+    }
+
+    let window = windows.single();
+    let (camera, camera_transform) = cameras.single();
+
+    if let (Some(pattern), Some((x, y))) = (
+        &clipboard.pattern,
+        cursor_tile(window, camera, camera_transform),
+    ) {
+        history.push(&board);
+        pattern.paste(&mut board, x, y, settings.brush.paint_mode);
+    }
+}
+
+fn render_ghost(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    ghosts: Query<Entity, With<Ghost>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    tool: Res<Tool>,
+    clipboard: Res<Clipboard>,
+) {
+    for entity in ghosts.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    if let Some(((x1, y1), (x2, y2))) = clipboard.selection {
+        let min = Vec2::new(x1.min(x2) as f32, y1.min(y2) as f32) * TILE_SIZE;
+        let max = Vec2::new(x1.max(x2) as f32 + 1., y1.max(y2) as f32 + 1.) * TILE_SIZE;
+        gizmos.rect_2d((min + max) / 2., 0., max - min, Color::WHITE);
+    }
+
+    if *tool != Tool::Paste {
+        return;
+    }
+
+    let window = windows.single();
+    let (camera, camera_transform) = cameras.single();
+    let (Some(pattern), Some((x, y))) = (
+        &clipboard.pattern,
+        cursor_tile(window, camera, camera_transform),
+    ) else {
+        return;
+    };
+
+    let (origin_x, origin_y) = pattern.origin(x, y);
+    for px in 0..pattern.width {
+        for py in 0..pattern.height {
+            let tile_type = pattern.get(px, py);
+            if tile_type == TileType::None {
+                continue;
+            }
+
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Tile::from_type(tile_type).color.with_a(0.5),
+                        custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(
+                        (origin_x + px as isize) as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+                        (origin_y + py as isize) as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+                        1.0,
+                    ),
+                    ..Default::default()
+                },
+                Ghost,
+            ));
+        }
+    }
+}
+
+fn clipboard_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut tool: ResMut<Tool>,
+    mut clipboard: ResMut<Clipboard>,
+    board: Res<Board>,
+) {
+    egui::Window::new("Clipboard")
+        .open(&mut settings.ui.clipboard)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Tool");
+            ui.radio_value(&mut *tool, Tool::Brush, "Brush");
+            ui.radio_value(&mut *tool, Tool::Select, "Select");
+            ui.add_enabled_ui(clipboard.pattern.is_some(), |ui| {
+                ui.radio_value(&mut *tool, Tool::Paste, "Paste");
+            });
+
+            ui.label("");
+            if ui
+                .add_enabled(clipboard.selection.is_some(), egui::Button::new("Copy"))
+                .clicked()
+            {
+                clipboard.copy(&board);
+            }
+            ui.add_enabled_ui(clipboard.pattern.is_some(), |ui| {
+                if ui.button("Rotate").clicked() {
+                    clipboard.transform(Pattern::rotate);
+                }
+                if ui.button("Flip horizontal").clicked() {
+                    clipboard.transform(Pattern::flip_horizontal);
+                }
+                if ui.button("Flip vertical").clicked() {
+                    clipboard.transform(Pattern::flip_vertical);
+                }
+            });
+
+            if let Some(pattern) = &clipboard.pattern {
+                ui.label(format!("\nClipboard: {}x{}", pattern.width, pattern.height));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 pattern with every tile different, so any misplaced tile shows.
+    fn pattern() -> Pattern {
+        Pattern {
+            width: 3,
+            height: 2,
+            tiles: vec![
+                vec![TileType::Sand, TileType::Water],
+                vec![TileType::Rock, TileType::Fire],
+                vec![TileType::Wood, TileType::None],
+            ],
+        }
+    }
+
+    #[test]
+    fn rotating_four_times_gives_back_the_pattern() {
+        let rotated = pattern().rotate();
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        rotated.validate().unwrap();
+        // Clockwise, so the top left corner ends up at the top right.
+        assert_eq!(rotated.get(1, 2), TileType::Water);
+        assert_eq!(rotated.get(0, 0), TileType::Wood);
+
+        assert_eq!(rotated.rotate().rotate().rotate(), pattern());
+    }
+
+    #[test]
+    fn flipping_twice_gives_back_the_pattern() {
+        let flipped = pattern().flip_horizontal();
+        assert_eq!(flipped.get(0, 0), TileType::Wood);
+        assert_eq!(flipped.flip_horizontal(), pattern());
+
+        let flipped = pattern().flip_vertical();
+        assert_eq!(flipped.get(0, 0), TileType::Water);
+        assert_eq!(flipped.flip_vertical(), pattern());
+    }
+
+    #[test]
+    fn regions_can_be_selected_from_any_corner() {
+        let mut board = Board::new(6, 6);
+        pattern().paste(&mut board, 3, 3, PaintMode::Overwrite);
+
+        let forwards = Pattern::from_region(&board, 2, 2, 4, 3);
+        assert_eq!(forwards, pattern());
+        assert_eq!(Pattern::from_region(&board, 4, 3, 2, 2), forwards);
+        assert_eq!(Pattern::from_region(&board, 2, 3, 4, 2), forwards);
+
+        // Corners past the board are clipped to it.
+        let clipped = Pattern::from_region(&board, 4, 4, 100, 100);
+        assert_eq!((clipped.width, clipped.height), (2, 2));
+    }
+
+    #[test]
+    fn pasting_over_the_edges_is_clipped() {
+        let mut board = Board::new(4, 4);
+        let big = Pattern {
+            width: 7,
+            height: 7,
+            tiles: vec![vec![TileType::Sand; 7]; 7],
+        };
+
+        for (x, y) in [(0, 0), (3, 3), (0, 3), (3, 0)] {
+            big.paste(&mut board, x, y, PaintMode::Overwrite);
+        }
+        assert!(board
+            .tiles
+            .iter()
+            .flatten()
+            .all(|tile| tile.tile_type == TileType::Sand));
+
+        // In fill mode only empty tiles are written, and empty pattern tiles skipped.
+        let mut board = Board::new(5, 5);
+        board.set(2, 1, Tile::from_type(TileType::Dirt)).unwrap();
+        board.set(3, 2, Tile::from_type(TileType::Acid)).unwrap();
+        pattern().paste(&mut board, 2, 2, PaintMode::Fill);
+        assert_eq!(board.get(1, 1).unwrap().tile_type, TileType::Sand);
+        assert_eq!(board.get(2, 1).unwrap().tile_type, TileType::Dirt);
+        assert_eq!(board.get(3, 2).unwrap().tile_type, TileType::Acid);
+    }
+}
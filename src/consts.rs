@@ -1,7 +1,18 @@
 pub const WIDTH: f32 = 1920.;
 pub const HEIGHT: f32 = 1080.;
-pub const TILE_HEIGHT: usize = 100;
-pub const TILE_WIDTH: usize = 100;
 pub const TILE_SIZE: f32 = 20.;
 pub const COLOR_VARIATION: f32 = 1. / 10.;
 pub const SETTINGS_PATH: &str = "settings.json";
+pub const STAMPS_PATH: &str = "stamps";
+pub const BINDINGS_PATH: &str = "bindings.json";
+pub const BOARD_PATH: &str = "board.json";
+pub const STATS_PATH: &str = "stats.csv";
+#[cfg(test)]
+pub const SCENARIOS_PATH: &str = "scenarios";
+#[cfg(test)]
+pub const FORMATS_PATH: &str = "formats";
+#[cfg(test)]
+pub const WORLDS_PATH: &str = "worlds";
+pub const REPLAY_PATH: &str = "replay.json";
+pub const PNG_PATH: &str = "board.png";
+pub const AUTOSAVE_PATH: &str = "autosave";
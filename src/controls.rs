@@ -1,135 +1,482 @@
 use crate::{
+    bindings::{Action, Bindings},
     board::Board,
-    consts::{SETTINGS_PATH, TILE_SIZE},
-    tile::{Material, Tile, TileType, MATERIALS},
+    camera::world_to_tile,
+    clipboard::{Clipboard, Tool},
+    consts::{BOARD_PATH, PNG_PATH, REPLAY_PATH},
+    export::{self, GifRecording, Region},
+    generate::{generate, WorldParams, DEPTH_RANGE, FRACTION_RANGE},
+    physics::{SimRng, Simulation},
+    replay::Recorder,
+    settings::{
+        PaintMode, RenderMode, Settings, SettingsErrors, AUTOSAVE_INTERVAL_RANGE,
+        AUTOSAVE_KEEP_RANGE, BOARD_SIZE_RANGE, RADIUS_RANGE, TICK_RATE_RANGE,
+    },
+    tile::{Tile, TileType, MATERIALS},
+    timeline::Timeline,
 };
-use bevy::{prelude::*, time::common_conditions::on_timer, window::PrimaryWindow};
+use bevy::{prelude::*, render::view::screenshot::ScreenshotManager, window::PrimaryWindow};
 use bevy_egui::{egui, EguiContexts};
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::{fs::File, str::FromStr};
-use std::{io::Write, time::Duration};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const UNDO_LIMIT: usize = 32;
 
 pub struct ControlsPlugin;
 
 impl Plugin for ControlsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup).add_systems(
-            Update,
-            (
-                mouse_controls,
-                settings_window,
-                save_settings.run_if(on_timer(Duration::from_secs(3))),
-            ),
-        );
+        app.init_resource::<UndoHistory>()
+            .init_resource::<NewBoardDialog>()
+            .add_systems(
+                Update,
+                (
+                    mouse_controls,
+                    keyboard_controls,
+                    settings_window.run_if(ui_visible),
+                    new_board_window.run_if(ui_visible),
+                ),
+            );
     }
 }
 
-#[derive(Resource, Serialize, Deserialize)]
-struct Settings {
-    tile_type: TileType,
-    radius: isize,
+pub fn ui_visible(settings: Res<Settings>) -> bool {
+    settings.ui.visible
 }
 
-impl Settings {
-    fn save_to_file(&self, path: PathBuf) -> std::io::Result<()> {
-        let serialized = serde_json::to_string_pretty(self)?;
-        File::create(path)?.write_all(serialized.as_bytes())?;
-        Ok(())
-    }
-
-    fn load_from_file(path: PathBuf) -> serde_json::Result<Self> {
-        let contents = std::fs::read_to_string(path).unwrap_or_else(|err| {
-            Settings::save_to_file(
-                &Settings {
-                    tile_type: TileType::Sand,
-                    radius: 0,
-                },
-                PathBuf::from_str(SETTINGS_PATH).unwrap(),
-            );
-            return "".to_string();
-        });
-        serde_json::from_str::<Self>(&contents)
-    }
+/// Board states from before each edit, most recent last.
+#[derive(Resource, Default)]
+pub struct UndoHistory {
+    boards: Vec<Vec<Vec<Tile>>>,
 }
 
-fn setup(mut commands: Commands) {
-    let mut settings = Settings {
-        tile_type: TileType::Sand,
-        radius: 0,
-    };
-    if let Ok(saved_settings) = Settings::load_from_file(PathBuf::from_str(SETTINGS_PATH).unwrap())
-    {
-        settings = saved_settings
+impl UndoHistory {
+    pub fn push(&mut self, board: &Board) {
+        if self.boards.len() >= UNDO_LIMIT {
+            self.boards.remove(0);
+        }
+        self.boards.push(board.tiles.clone());
+    }
+
+    pub fn undo(&mut self, board: &mut Board) {
+        if let Some(tiles) = self.boards.pop() {
+            board.replace_tiles(tiles);
+        }
+    }
+
+    /// Forgets every edit, for when the board is replaced with an unrelated one.
+    pub fn clear(&mut self) {
+        self.boards.clear();
     }
-    commands.insert_resource(settings);
 }
 
-fn save_settings(settings: Res<Settings>) {
-    Settings::save_to_file(&settings, PathBuf::from_str(SETTINGS_PATH).unwrap()).unwrap_or_else(
-        |err| {
-            println!("Failed to save settings :(");
-            println!("{:#?}", err);
-        },
-    );
+/// The "New board" window, opened from the settings window.
+#[derive(Resource, Default)]
+struct NewBoardDialog {
+    open: bool,
+    /// Generate terrain rather than start with an empty box.
+    generate: bool,
+    params: WorldParams,
+}
+
+/// The settings window's PNG export options and the result of the last export.
+struct PngExport {
+    scale: u32,
+    message: Option<String>,
+}
+
+impl Default for PngExport {
+    fn default() -> Self {
+        PngExport {
+            scale: 4,
+            message: None,
+        }
+    }
 }
 
-fn settings_window(mut contexts: EguiContexts, mut settings: ResMut<Settings>) {
+#[allow(clippy::too_many_arguments)]
+fn settings_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut simulation: ResMut<Simulation>,
+    board: Res<Board>,
+    errors: Res<SettingsErrors>,
+    mut recorder: ResMut<Recorder>,
+    mut rng: ResMut<SimRng>,
+    mut replay_message: Local<Option<String>>,
+    mut png_export: Local<PngExport>,
+    mut gif: ResMut<GifRecording>,
+    clipboard: Res<Clipboard>,
+    mut new_board: ResMut<NewBoardDialog>,
+) {
     egui::Window::new("Settings").show(contexts.ctx_mut(), |ui| {
+        for error in errors.0.iter() {
+            ui.colored_label(egui::Color32::LIGHT_RED, error.to_string());
+        }
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut simulation.paused, "Paused");
+            if ui.button("Step").clicked() {
+                simulation.step = true;
+            }
+        });
+        if let Some(violation) = &simulation.violation {
+            ui.colored_label(egui::Color32::LIGHT_RED, violation.to_string());
+        }
         ui.label("Material");
         for mat in MATERIALS.iter() {
             let current_item = mat.tile_type;
 
-            ui.radio_value(&mut settings.tile_type, current_item, mat.name);
+            ui.radio_value(&mut settings.brush.tile_type, current_item, mat.name);
         }
         ui.label("\nRadius");
-        ui.add(egui::Slider::new(&mut settings.radius, 0..=4))
+        ui.add(egui::Slider::new(&mut settings.brush.radius, RADIUS_RANGE));
+        ui.label("\nPaint mode");
+        ui.radio_value(
+            &mut settings.brush.paint_mode,
+            PaintMode::Fill,
+            "Fill empty",
+        );
+        ui.radio_value(
+            &mut settings.brush.paint_mode,
+            PaintMode::Overwrite,
+            "Overwrite",
+        );
+
+        ui.collapsing("Simulation", |ui| {
+            ui.add(egui::Slider::new(&mut settings.tick_rate, TICK_RATE_RANGE).text("Ticks/s"));
+            ui.horizontal(|ui| {
+                let mut fixed_seed = settings.seed.is_some();
+                ui.checkbox(&mut fixed_seed, "Fixed seed");
+                match (fixed_seed, settings.seed) {
+                    (true, None) => settings.seed = Some(0),
+                    (false, Some(_)) => settings.seed = None,
+                    _ => {}
+                }
+                if let Some(seed) = settings.seed.as_mut() {
+                    seed_edit(ui, "simulation seed", seed);
+                }
+            });
+            if ui
+                .checkbox(&mut simulation.check_invariants, "Check invariants")
+                .changed()
+            {
+                simulation.violation = None;
+            }
+            ui.horizontal(|ui| {
+                if !recorder.is_recording() {
+                    if ui.button("Record replay").clicked() {
+                        let seed = settings.seed.unwrap_or_else(rand::random);
+                        recorder.start(&board, &simulation, &mut rng, seed);
+                        *replay_message = None;
+                    }
+                } else if ui.button("Stop and save replay").clicked() {
+                    if let Some(replay) = recorder.stop(&board, &simulation) {
+                        *replay_message = Some(match replay.save_to_file(Path::new(REPLAY_PATH)) {
+                            Ok(()) => format!("Saved {}", REPLAY_PATH),
+                            Err(err) => format!("Couldn't save {}: {}", REPLAY_PATH, err),
+                        });
+                    }
+                }
+                if let Some(message) = replay_message.as_ref() {
+                    ui.label(message);
+                }
+            });
+        });
+
+        ui.collapsing("Board", |ui| {
+            if ui.button("New board...").clicked() {
+                new_board.open = true;
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save PNG").clicked() {
+                    png_export.message = Some(
+                        match export::save_png(&board, Path::new(PNG_PATH), png_export.scale) {
+                            Ok(()) => format!("Saved {}", PNG_PATH),
+                            Err(err) => err,
+                        },
+                    );
+                }
+                ui.add(
+                    egui::DragValue::new(&mut png_export.scale)
+                        .clamp_range(1..=32)
+                        .suffix(" px per tile"),
+                );
+            });
+            if let Some(message) = png_export.message.as_ref() {
+                ui.label(message);
+            }
+            ui.horizontal(|ui| {
+                let label = if gif.is_recording() {
+                    "Stop GIF"
+                } else {
+                    "Record GIF"
+                };
+                if ui.button(label).clicked() {
+                    let selection = clipboard
+                        .selection
+                        .map(|(start, end)| Region::from_corners(start, end));
+                    gif.toggle(&board, selection, settings.tick_rate);
+                }
+                ui.add_enabled_ui(!gif.is_recording(), |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut gif.every)
+                            .clamp_range(1..=600)
+                            .prefix("every ")
+                            .suffix(" ticks"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut gif.scale)
+                            .clamp_range(1..=32)
+                            .suffix(" px per tile"),
+                    );
+                });
+            });
+            if let Some(message) = gif.message.as_ref() {
+                ui.label(message);
+            }
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut settings.autosave.enabled, "Autosave");
+                ui.add_enabled_ui(settings.autosave.enabled, |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut settings.autosave.interval)
+                            .clamp_range(AUTOSAVE_INTERVAL_RANGE)
+                            .prefix("every ")
+                            .suffix(" s"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut settings.autosave.keep)
+                            .clamp_range(AUTOSAVE_KEEP_RANGE)
+                            .prefix("keeping ")
+                            .suffix(" files"),
+                    );
+                });
+            });
+        });
+
+        ui.collapsing("Display", |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut settings.render_mode, RenderMode::Normal, "Normal");
+                ui.radio_value(&mut settings.render_mode, RenderMode::Flat, "Flat");
+                ui.radio_value(&mut settings.render_mode, RenderMode::Dirty, "Changes");
+            });
+            ui.checkbox(&mut settings.ui.clipboard, "Clipboard window");
+            ui.checkbox(&mut settings.ui.stamps, "Stamps window");
+            ui.checkbox(&mut settings.ui.inspector, "Inspector window");
+            ui.checkbox(&mut settings.ui.bindings, "Bindings window");
+            ui.checkbox(&mut settings.ui.stats, "Stats window");
+            ui.checkbox(&mut settings.ui.timeline, "Timeline window");
+        });
     });
 }
 
+/// A text box for a seed. A `DragValue` goes through `f64`, which can't hold every
+/// `u64`, so big seeds would be rounded. Text that isn't a seed is put back when the box
+/// loses focus.
+fn seed_edit(ui: &mut egui::Ui, id_source: &str, seed: &mut u64) {
+    let id = ui.make_persistent_id(id_source);
+    let editing = ui.memory(|memory| memory.has_focus(id));
+    let mut text = match ui.data_mut(|data| data.get_temp::<String>(id)) {
+        Some(text) if editing => text,
+        _ => seed.to_string(),
+    };
+    ui.add(
+        egui::TextEdit::singleline(&mut text)
+            .id(id)
+            .desired_width(160.),
+    );
+    if let Ok(parsed) = text.trim().parse() {
+        *seed = parsed;
+    }
+    ui.data_mut(|data| data.insert_temp(id, text));
+}
+
+fn new_board_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut board: ResMut<Board>,
+    mut dialog: ResMut<NewBoardDialog>,
+    mut history: ResMut<UndoHistory>,
+    mut timeline: ResMut<Timeline>,
+) {
+    let mut open = dialog.open;
+    let mut create = false;
+    egui::Window::new("New board")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Slider::new(&mut settings.board.width, BOARD_SIZE_RANGE).text("Width"));
+            ui.add(egui::Slider::new(&mut settings.board.height, BOARD_SIZE_RANGE).text("Height"));
+            ui.checkbox(&mut dialog.generate, "Generate terrain");
+
+            let generate = dialog.generate;
+            let params = &mut dialog.params;
+            ui.add_enabled_ui(generate, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Seed");
+                    seed_edit(ui, "terrain seed", &mut params.seed);
+                    if ui.button("Random").clicked() {
+                        params.seed = rand::random();
+                    }
+                });
+                ui.add(egui::Slider::new(&mut params.ground, FRACTION_RANGE).text("Ground height"));
+                ui.add(egui::Slider::new(&mut params.hills, FRACTION_RANGE).text("Hills"));
+                ui.add(egui::Slider::new(&mut params.sand_depth, DEPTH_RANGE).text("Sand rows"));
+                ui.add(egui::Slider::new(&mut params.dirt_depth, DEPTH_RANGE).text("Dirt rows"));
+                ui.add(egui::Slider::new(&mut params.bedrock, DEPTH_RANGE).text("Bedrock rows"));
+                ui.add(egui::Slider::new(&mut params.caves, FRACTION_RANGE).text("Caves"));
+                ui.add(egui::Slider::new(&mut params.water, FRACTION_RANGE).text("Water"));
+                ui.add(egui::Slider::new(&mut params.trees, FRACTION_RANGE).text("Trees"));
+                if ui.button("Reset").clicked() {
+                    *params = WorldParams {
+                        seed: params.seed,
+                        ..WorldParams::default()
+                    };
+                }
+            });
+
+            create = ui.button("Create").clicked();
+        });
+
+    if create {
+        let (width, height) = (settings.board.width, settings.board.height);
+        let new_board = if dialog.generate {
+            generate(width, height, &dialog.params)
+        } else {
+            Board::new(width, height)
+        };
+        board.replace_tiles(new_board.tiles);
+        history.clear();
+        timeline.clear();
+        open = false;
+    }
+    dialog.open = open;
+}
+
+/// Returns the board tile under the cursor, if the cursor is over the window.
+pub fn cursor_tile(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<(usize, usize)> {
+    let cursor_position = window.cursor_position()?;
+    let world_position = camera.viewport_to_world_2d(camera_transform, cursor_position)?;
+
+    world_to_tile(world_position)
+}
+
+/// Whether the pointer is over an egui window or being used by one, so a click there is
+/// meant for the window rather than the board underneath.
+pub fn pointer_over_ui(contexts: &mut EguiContexts) -> bool {
+    let ctx = contexts.ctx_mut();
+    ctx.wants_pointer_input() || ctx.is_pointer_over_area()
+}
+
+#[allow(clippy::too_many_arguments)]
 fn mouse_controls(
+    mut contexts: EguiContexts,
     mouse: Res<ButtonInput<MouseButton>>,
     windows: Query<&Window, With<PrimaryWindow>>,
     cameras: Query<(&Camera, &GlobalTransform)>,
     mut board: ResMut<Board>,
     settings: Res<Settings>,
+    tool: Res<Tool>,
+    mut history: ResMut<UndoHistory>,
+    mut recorder: ResMut<Recorder>,
+    simulation: Res<Simulation>,
 ) {
+    if *tool != Tool::Brush || pointer_over_ui(&mut contexts) {
+        return;
+    }
+
     let window = windows.single();
     let (camera, camera_transform) = cameras.single();
 
+    if mouse.any_just_pressed([MouseButton::Left, MouseButton::Right]) {
+        history.push(&board);
+    }
+
     if mouse.pressed(MouseButton::Left) || mouse.pressed(MouseButton::Right) {
-        if let Some(cursor_position) = window.cursor_position() {
-            if let Some(world_position) = camera
-                .viewport_to_world(camera_transform, cursor_position)
-                .map(|ray| ray.origin.truncate())
-            {
-                let world_position = world_position - Vec2::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
-                let tile_x = (world_position.x / TILE_SIZE).round() as usize;
-                let tile_y = (world_position.y / TILE_SIZE).round() as usize;
-                let tile = if mouse.pressed(MouseButton::Left) {
-                    TileType::Sand
-                } else {
-                    TileType::None
-                };
-                println!("World position: {:?}", world_position);
-                println!("Tile position: ({}, {})", tile_x, tile_y);
-                if board
+        if let Some((tile_x, tile_y)) = cursor_tile(window, camera, camera_transform) {
+            if settings.brush.paint_mode == PaintMode::Overwrite
+                || board
                     .get(tile_x, tile_y)
                     .unwrap_or(Tile::from_type(TileType::None))
                     .tile_type
                     == TileType::None
-                    || mouse.pressed(MouseButton::Right)
-                {
-                    let _ = board.set_radius(
-                        tile_x,
-                        tile_y,
-                        Tile::from_type(settings.tile_type),
-                        settings.radius,
-                    );
-                } else {
-                    board.dirty_tiles.push((tile_x, tile_y));
-                }
+                || mouse.pressed(MouseButton::Right)
+            {
+                let _ = board.set_radius(
+                    tile_x,
+                    tile_y,
+                    Tile::from_type(settings.brush.tile_type),
+                    settings.brush.radius,
+                );
+                recorder.brush(
+                    &simulation,
+                    tile_x,
+                    tile_y,
+                    settings.brush.tile_type,
+                    settings.brush.radius,
+                );
+            } else {
+                board.dirty_tiles.push((tile_x, tile_y));
             }
         }
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+fn keyboard_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    mut settings: ResMut<Settings>,
+    mut simulation: ResMut<Simulation>,
+    mut history: ResMut<UndoHistory>,
+    mut board: ResMut<Board>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+) {
+    for (action, material) in Action::MATERIALS.iter().zip(MATERIALS.iter()) {
+        if bindings.just_pressed(*action, &keys) {
+            settings.brush.tile_type = material.tile_type;
+        }
+    }
+
+    if bindings.just_pressed(Action::BrushBigger, &keys) {
+        settings.brush.radius = (settings.brush.radius + 1).min(*RADIUS_RANGE.end());
+    }
+    if bindings.just_pressed(Action::BrushSmaller, &keys) {
+        settings.brush.radius = (settings.brush.radius - 1).max(*RADIUS_RANGE.start());
+    }
+    if bindings.just_pressed(Action::Pause, &keys) {
+        simulation.paused = !simulation.paused;
+    }
+    if bindings.just_pressed(Action::Step, &keys) {
+        simulation.step = true;
+    }
+    if bindings.just_pressed(Action::Undo, &keys) {
+        history.undo(&mut board);
+    }
+    if bindings.just_pressed(Action::Save, &keys) {
+        board
+            .save_to_file(Path::new(BOARD_PATH))
+            .unwrap_or_else(|err| {
+                println!("Failed to save board :(");
+                println!("{:#?}", err);
+            });
+    }
+    if bindings.just_pressed(Action::ToggleUi, &keys) {
+        settings.ui.visible = !settings.ui.visible;
+    }
+    if bindings.just_pressed(Action::Screenshot, &keys) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        screenshots
+            .save_screenshot_to_disk(windows.single(), format!("screenshot-{}.png", time))
+            .unwrap_or_else(|err| {
+                println!("Failed to take screenshot :(");
+                println!("{:#?}", err);
+            });
+    }
+}
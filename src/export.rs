@@ -0,0 +1,389 @@
+use crate::{
+    bindings::{Action, Bindings},
+    board::Board,
+    clipboard::Clipboard,
+    gif::GifWriter,
+    physics::{physics, Simulation},
+    settings::Settings,
+};
+use bevy::prelude::*;
+use image::{Rgb, RgbImage};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A rectangle of tiles, from its bottom left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn whole(board: &Board) -> Self {
+        Region {
+            x: 0,
+            y: 0,
+            width: board.width(),
+            height: board.height(),
+        }
+    }
+
+    /// The region between two opposite corners, both included.
+    pub fn from_corners((x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> Self {
+        Region {
+            x: x1.min(x2),
+            y: y1.min(y2),
+            width: x1.abs_diff(x2) + 1,
+            height: y1.abs_diff(y2) + 1,
+        }
+    }
+
+    pub fn fits(&self, board: &Board) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.x + self.width <= board.width()
+            && self.y + self.height <= board.height()
+    }
+}
+
+/// Part of the board as an image, one `scale`x`scale` square of pixels per tile in the
+/// tile's own colour. Built on the CPU, so it works without a window or a GPU.
+pub fn region_image(board: &Board, region: Region, scale: u32) -> RgbImage {
+    let scale = scale.max(1);
+    let top = region.y + region.height - 1;
+
+    RgbImage::from_fn(
+        region.width as u32 * scale,
+        region.height as u32 * scale,
+        |pixel_x, pixel_y| {
+            // Images start at the top, boards at the bottom.
+            let x = region.x + (pixel_x / scale) as usize;
+            let y = top - (pixel_y / scale) as usize;
+            let [r, g, b, _] = board.tiles[x][y].color.as_rgba_u8();
+            Rgb([r, g, b])
+        },
+    )
+}
+
+pub fn board_image(board: &Board, scale: u32) -> RgbImage {
+    region_image(board, Region::whole(board), scale)
+}
+
+pub fn save_png(board: &Board, path: &Path, scale: u32) -> Result<(), String> {
+    board_image(board, scale)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))
+}
+
+/// Writes every `every`th tick to `dir` as `frame-000000.png`, `frame-000001.png`, ...
+/// numbered without gaps so video tools can take them as a sequence.
+pub struct FrameWriter {
+    dir: PathBuf,
+    every: u64,
+    scale: u32,
+    next_frame: u64,
+}
+
+impl FrameWriter {
+    pub fn new(dir: &Path, every: u64, scale: u32) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("Couldn't create {}: {}", dir.display(), err))?;
+        Ok(FrameWriter {
+            dir: dir.to_path_buf(),
+            every: every.max(1),
+            scale,
+            next_frame: 0,
+        })
+    }
+
+    /// Saves the board if `tick` is one of the ticks being recorded.
+    pub fn tick(&mut self, board: &Board, tick: u64) -> Result<(), String> {
+        if !tick.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        let path = self.dir.join(format!("frame-{:06}.png", self.next_frame));
+        save_png(board, &path, self.scale)?;
+        self.next_frame += 1;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.next_frame
+    }
+}
+
+/// Records frames from the running app with `--frames`, windowed or headless.
+pub struct FramesPlugin {
+    pub dir: PathBuf,
+    pub every: u64,
+    pub scale: u32,
+}
+
+#[derive(Resource)]
+struct Frames {
+    writer: FrameWriter,
+    last_tick: Option<u64>,
+}
+
+impl Plugin for FramesPlugin {
+    fn build(&self, app: &mut App) {
+        let writer = FrameWriter::new(&self.dir, self.every, self.scale).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        });
+
+        app.insert_resource(Frames {
+            writer,
+            last_tick: None,
+        })
+        .add_systems(FixedUpdate, record_frames.after(physics));
+    }
+}
+
+fn record_frames(board: Res<Board>, simulation: Res<Simulation>, mut frames: ResMut<Frames>) {
+    if frames.last_tick == Some(simulation.tick) {
+        return;
+    }
+    frames.last_tick = Some(simulation.tick);
+
+    if let Err(err) = frames.writer.tick(&board, simulation.tick) {
+        eprintln!("{}", err);
+    }
+}
+
+/// How long to show each frame of a GIF, in hundredths of a second, so it plays back at
+/// the speed the simulation ran.
+pub fn gif_delay(every: u64, tick_rate: f64) -> u16 {
+    // Most viewers slow anything shorter than 2 right down.
+    (every as f64 * 100. / tick_rate)
+        .round()
+        .clamp(2., u16::MAX as f64) as u16
+}
+
+/// Writes every `every`th tick of a region of the board to an animated GIF.
+pub struct GifRecorder {
+    path: PathBuf,
+    writer: GifWriter<BufWriter<File>>,
+    region: Region,
+    every: u64,
+    scale: u32,
+    frames: u64,
+}
+
+impl GifRecorder {
+    pub fn new(
+        path: &Path,
+        region: Region,
+        every: u64,
+        scale: u32,
+        delay: u16,
+    ) -> Result<Self, String> {
+        let scale = scale.max(1);
+        let size = |tiles: usize| {
+            u16::try_from(tiles * scale as usize)
+                .map_err(|_| format!("{} pixels is too big for a GIF", tiles * scale as usize))
+        };
+        let (width, height) = (size(region.width)?, size(region.height)?);
+
+        let file = File::create(path)
+            .map_err(|err| format!("Couldn't create {}: {}", path.display(), err))?;
+        let writer = GifWriter::new(BufWriter::new(file), width, height, delay)
+            .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))?;
+
+        Ok(GifRecorder {
+            path: path.to_path_buf(),
+            writer,
+            region,
+            every: every.max(1),
+            scale,
+            frames: 0,
+        })
+    }
+
+    /// Adds the board as a frame if `tick` is one of the ticks being recorded.
+    pub fn tick(&mut self, board: &Board, tick: u64) -> Result<(), String> {
+        if !tick.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        if !self.region.fits(board) {
+            return Err(format!(
+                "The board no longer covers the region {} is recording",
+                self.path.display()
+            ));
+        }
+
+        let image = region_image(board, self.region, self.scale);
+        let pixels: Vec<[u8; 3]> = image.pixels().map(|pixel| pixel.0).collect();
+        self.writer
+            .write_frame(&pixels)
+            .map_err(|err| format!("Couldn't write {}: {}", self.path.display(), err))?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes the file and returns how many frames it has.
+    pub fn finish(self) -> Result<u64, String> {
+        self.writer
+            .finish()
+            .map_err(|err| format!("Couldn't write {}: {}", self.path.display(), err))?;
+        Ok(self.frames)
+    }
+}
+
+/// Records GIFs from the running app: from the start with `--gif`, or from the record
+/// button and hotkey in a window.
+pub struct GifPlugin {
+    pub path: Option<PathBuf>,
+    pub region: Option<Region>,
+    pub every: u64,
+    pub scale: u32,
+}
+
+/// The GIF being recorded, if any, and the options the next one starts with.
+#[derive(Resource)]
+pub struct GifRecording {
+    recorder: Option<GifRecorder>,
+    /// Started on the first tick, once there's a board to check it against.
+    pending: Option<(PathBuf, Option<Region>)>,
+    last_tick: Option<u64>,
+    pub every: u64,
+    pub scale: u32,
+    /// Result of the last recording, for the settings window.
+    pub message: Option<String>,
+}
+
+impl Plugin for GifPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GifRecording {
+            recorder: None,
+            pending: self.path.clone().map(|path| (path, self.region)),
+            last_tick: None,
+            every: self.every,
+            scale: self.scale,
+            message: None,
+        })
+        .add_systems(FixedUpdate, record_gif.after(physics));
+
+        if app.is_plugin_added::<WindowPlugin>() {
+            app.add_systems(Update, gif_hotkey);
+        }
+    }
+}
+
+impl GifRecording {
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Starts recording `region`, or the whole board, to `path`.
+    pub fn start(
+        &mut self,
+        path: &Path,
+        region: Option<Region>,
+        board: &Board,
+        tick_rate: f64,
+    ) -> Result<(), String> {
+        let region = region.unwrap_or_else(|| Region::whole(board));
+        if !region.fits(board) {
+            return Err(format!(
+                "{}x{} at ({}, {}) doesn't fit on the {}x{} board",
+                region.width,
+                region.height,
+                region.x,
+                region.y,
+                board.width(),
+                board.height()
+            ));
+        }
+
+        let delay = gif_delay(self.every, tick_rate);
+        self.recorder = Some(GifRecorder::new(
+            path, region, self.every, self.scale, delay,
+        )?);
+        self.last_tick = None;
+        self.message = Some(format!("Recording {}", path.display()));
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let path = recorder.path().display().to_string();
+        self.message = Some(match recorder.finish() {
+            Ok(frames) => format!("Saved {} frames to {}", frames, path),
+            Err(err) => err,
+        });
+    }
+
+    /// Starts recording a new timestamped file if nothing is being recorded, otherwise
+    /// stops. Records the selection if there is one.
+    pub fn toggle(&mut self, board: &Board, selection: Option<Region>, tick_rate: f64) {
+        if self.is_recording() {
+            self.stop();
+            return;
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = PathBuf::from(format!("recording-{}.gif", time));
+        if let Err(err) = self.start(&path, selection, board, tick_rate) {
+            self.message = Some(err);
+        }
+    }
+}
+
+fn record_gif(
+    board: Res<Board>,
+    simulation: Res<Simulation>,
+    settings: Res<Settings>,
+    mut recording: ResMut<GifRecording>,
+) {
+    if let Some((path, region)) = recording.pending.take() {
+        if let Err(err) = recording.start(&path, region, &board, settings.tick_rate) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if recording.last_tick == Some(simulation.tick) {
+        return;
+    }
+    recording.last_tick = Some(simulation.tick);
+
+    let Some(recorder) = recording.recorder.as_mut() else {
+        return;
+    };
+    if let Err(err) = recorder.tick(&board, simulation.tick) {
+        eprintln!("{}", err);
+        recording.recorder = None;
+        recording.message = Some(err);
+    }
+}
+
+fn gif_hotkey(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    board: Res<Board>,
+    clipboard: Res<Clipboard>,
+    settings: Res<Settings>,
+    mut recording: ResMut<GifRecording>,
+) {
+    if bindings.just_pressed(Action::RecordGif, &keys) {
+        let selection = clipboard
+            .selection
+            .map(|(start, end)| Region::from_corners(start, end));
+        recording.toggle(&board, selection, settings.tick_rate);
+    }
+}
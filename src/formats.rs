@@ -0,0 +1,211 @@
+//! Checks boards survive saving and loading in every format, and that the `.board`
+//! files in `formats/` still load as the boards in their `.expected.txt`. Files saved by
+//! an older version stay there so they keep loading. Run with `BLESS=1` to write the
+//! `.expected.txt` of a newly added `.board` file.
+
+use crate::{
+    binary::{is_binary_path, BINARY_VERSION},
+    board::Board,
+    consts::FORMATS_PATH,
+    tile::{Tile, TileType, MATERIALS},
+};
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{env, fmt::Write, fs, path::PathBuf};
+
+/// The board's tiles as text, followed by every colour and strength that isn't its
+/// material's default, so two boards are the same if their descriptions are.
+fn describe(board: &Board) -> String {
+    let mut description = board.to_ascii();
+    for (x, column) in board.tiles.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            let [r, g, b, a] = tile.color.as_rgba_u8();
+            if [r, g, b] != tile.tile_type.base_color().as_rgba_u8()[..3] {
+                let _ = writeln!(
+                    description,
+                    "color {} {} #{:02x}{:02x}{:02x}{:02x}",
+                    x, y, r, g, b, a
+                );
+            }
+            if tile.strength != Tile::from_type(tile.tile_type).strength {
+                let _ = writeln!(description, "strength {} {} {:?}", x, y, tile.strength);
+            }
+        }
+    }
+    description
+}
+
+/// A board with every kind of tile, including ones whose colour and strength are
+/// nothing the game would pick.
+fn random_board(width: usize, height: usize, rng: &mut StdRng) -> Board {
+    let tiles = (0..width)
+        .map(|_| {
+            (0..height)
+                .map(|_| {
+                    let mut tile = Tile::from_type(MATERIALS.choose(rng).unwrap().tile_type);
+                    if rng.gen_bool(0.2) {
+                        let alpha = tile.tile_type.base_color().a();
+                        tile.color = Color::rgb_u8(rng.gen(), rng.gen(), rng.gen()).with_a(alpha);
+                    }
+                    if rng.gen_bool(0.2) {
+                        tile.strength = rng.gen_bool(0.8).then(|| rng.gen());
+                    }
+                    tile
+                })
+                .collect()
+        })
+        .collect();
+
+    Board {
+        tiles,
+        dirty_tiles: Vec::new(),
+    }
+}
+
+/// Long runs of one material, so run lengths take more than one byte.
+fn sparse_board(width: usize, height: usize) -> Board {
+    let mut board = Board::new(width, height);
+    board.tiles[width / 2][height / 2] = Tile::from_type(TileType::Fire);
+    board.tiles[width - 1][height - 1] = Tile::from_type(TileType::Sand);
+    board
+}
+
+/// Saves and loads `board` in every format, checking nothing is lost that the format
+/// keeps.
+fn round_trip(board: &Board) -> Result<(), String> {
+    let bytes = board.to_binary();
+    let loaded = Board::from_binary(&bytes)?;
+    if describe(&loaded) != describe(board) {
+        return Err("the binary format changed the board".into());
+    }
+    // Any shorter file is missing something it says is there.
+    let step = bytes.len() / 32 + 1;
+    let mut lengths = (0..bytes.len()).step_by(step).chain([bytes.len() - 1]);
+    if let Some(length) = lengths.find(|length| Board::from_binary(&bytes[..*length]).is_ok()) {
+        return Err(format!(
+            "the first {} of {} bytes of a binary board loaded",
+            length,
+            bytes.len()
+        ));
+    }
+
+    let loaded = Board::from_ascii(&board.to_ascii())?;
+    if loaded.tile_types() != board.tile_types() {
+        return Err("the text format changed the tile types".into());
+    }
+
+    let json = serde_json::to_string(&board.tile_types()).map_err(|err| err.to_string())?;
+    let tile_types: Vec<Vec<TileType>> =
+        serde_json::from_str(&json).map_err(|err| err.to_string())?;
+    if tile_types != board.tile_types() {
+        return Err("the JSON format changed the tile types".into());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn boards_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut boards = vec![
+        random_board(1, 1, &mut rng),
+        random_board(1, 40, &mut rng),
+        random_board(40, 1, &mut rng),
+        sparse_board(300, 200),
+    ];
+    for _ in 0..100 {
+        let (width, height) = (rng.gen_range(1..=64), rng.gen_range(1..=64));
+        boards.push(random_board(width, height, &mut rng));
+    }
+
+    for board in boards.iter() {
+        if let Err(err) = round_trip(board) {
+            panic!(
+                "round trip of a {}x{} board: {}",
+                board.width(),
+                board.height(),
+                err
+            );
+        }
+    }
+}
+
+#[test]
+fn huge_boards_are_refused_before_loading() {
+    let max = *crate::settings::BOARD_SIZE_RANGE.end();
+    let bytes = Board::new(3, 3).to_binary();
+    for (width, height) in [(max + 1, 3), (3, max + 1), (u32::MAX as usize, 1 << 20)] {
+        // Swap the 3x3 header, one byte per dimension, for a bigger one.
+        let mut header = bytes[..6].to_vec();
+        for mut value in [width, height] {
+            while value >= 0x80 {
+                header.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            header.push(value as u8);
+        }
+        let huge = [header.as_slice(), &bytes[8..]].concat();
+
+        let err = Board::from_binary(&huge).unwrap_err();
+        assert!(err.contains("more than"), "{}", err);
+    }
+}
+
+/// Loads every `.board` file in `formats/`, saved by this or an earlier build, and
+/// compares it with its description. Files saved in the current version must also be
+/// saved again byte for byte, so the format can't change without a version bump.
+#[test]
+fn saved_boards_still_load() {
+    let bless = env::var_os("BLESS").is_some();
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(FORMATS_PATH);
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_binary_path(path))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no .board files in {}", dir.display());
+
+    let mut failed = Vec::new();
+    for path in paths.iter() {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let expected_path = path.with_extension("expected.txt");
+        let bytes = fs::read(path).unwrap();
+
+        if bless && !expected_path.exists() {
+            let board = Board::from_binary(&bytes).unwrap();
+            fs::write(&expected_path, describe(&board)).unwrap();
+            println!("blessed {}", name);
+            continue;
+        }
+
+        let expected = fs::read_to_string(&expected_path)
+            .unwrap_or_else(|err| panic!("Couldn't read {}: {}", expected_path.display(), err));
+        let result = Board::from_binary(&bytes).and_then(|board| {
+            if describe(&board) != expected {
+                return Err("loads as a different board than expected".into());
+            }
+            let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+            if version == BINARY_VERSION && board.to_binary() != bytes {
+                return Err(format!(
+                    "saves differently, bump BINARY_VERSION from {} if that's intended",
+                    BINARY_VERSION
+                ));
+            }
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            println!("FAILED  {}: {}", name, err);
+            failed.push(name);
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "{} of {} saved boards failed: {}",
+        failed.len(),
+        paths.len(),
+        failed.join(", ")
+    );
+}
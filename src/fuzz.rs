@@ -0,0 +1,138 @@
+use crate::{
+    board::Board,
+    cli::FuzzArgs,
+    physics,
+    tile::{Tile, TileType, MATERIALS},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::panic::{self, AssertUnwindSafe};
+
+const MAX_SIZE: usize = 24;
+
+/// Picks a board size for a case. The first few are the awkward shapes, so every run
+/// covers them.
+fn board_size(case: u64, rng: &mut StdRng) -> (usize, usize) {
+    match case {
+        0 => (1, rng.gen_range(1..=MAX_SIZE)),
+        1 => (rng.gen_range(1..=MAX_SIZE), 1),
+        2 => (2, 2),
+        3 => (1, 1),
+        _ => (rng.gen_range(1..=MAX_SIZE), rng.gen_range(1..=MAX_SIZE)),
+    }
+}
+
+/// A board with no walls around it, about half of it filled with random materials.
+fn random_board(width: usize, height: usize, rng: &mut StdRng) -> Board {
+    let tiles = (0..width)
+        .map(|_| {
+            (0..height)
+                .map(|_| {
+                    let tile_type = if rng.gen::<bool>() {
+                        MATERIALS.choose(rng).unwrap().tile_type
+                    } else {
+                        TileType::None
+                    };
+                    Tile::from_type(tile_type)
+                })
+                .collect()
+        })
+        .collect();
+
+    Board {
+        tiles,
+        dirty_tiles: Vec::new(),
+    }
+}
+
+/// Steps `board` with invariant checking, turning panics into errors too.
+fn run_case(mut board: Board, seed: u64, ticks: u64) -> Result<(), String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for tick in 1..=ticks {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            physics::step_checked(&mut board, &mut rng)
+        }));
+
+        match result {
+            Ok(Ok(())) => board.clear_dirty_tiles(),
+            Ok(Err(violation)) => return Err(format!("tick {}: {}", tick, violation)),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".into());
+                return Err(format!("tick {}: panicked: {}", tick, message));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The boards to try for a given seed, each with its case number and the seed it's
+/// stepped with.
+fn cases(seed: u64, count: u64) -> impl Iterator<Item = (u64, Board, u64)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count).map(move |case| {
+        let (width, height) = board_size(case, &mut rng);
+        let board = random_board(width, height, &mut rng);
+        (case, board, rng.gen())
+    })
+}
+
+/// Runs `particles fuzz`. Stops at the first failing board and saves it, so it can be
+/// replayed with `particles sim --check`.
+pub fn run(args: &FuzzArgs) -> Result<(), String> {
+    for (case, board, case_seed) in cases(args.seed, args.cases) {
+        if let Err(err) = run_case(board.clone(), case_seed, args.ticks) {
+            board
+                .save_to_file(&args.out)
+                .map_err(|err| format!("Couldn't write {}: {}", args.out.display(), err))?;
+
+            println!("{}", board.to_ascii());
+            let replay = format!(
+                "particles sim --board {} --ticks {} --seed {} --out /dev/null --check",
+                args.out.display(),
+                args.ticks,
+                case_seed
+            );
+            return Err(format!(
+                "case {} ({}x{}) failed on {}\n\nReplay it with: {}",
+                case,
+                board.width(),
+                board.height(),
+                err,
+                replay
+            ));
+        }
+    }
+
+    println!(
+        "{} boards ran {} ticks each without breaking anything.",
+        args.cases, args.ticks
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_boards_keep_the_invariants() {
+        for seed in 0..4 {
+            for (case, board, case_seed) in cases(seed, 50) {
+                if let Err(err) = run_case(board.clone(), case_seed, 30) {
+                    panic!(
+                        "seed {} case {} failed on {}\n{}",
+                        seed,
+                        case,
+                        err,
+                        board.to_ascii()
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    board::Board,
+    cli::GenerateArgs,
+    tile::{Tile, TileType},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::ops::RangeInclusive;
+
+pub const FRACTION_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const DEPTH_RANGE: RangeInclusive<usize> = 0..=50;
+
+/// Tiles across one bump of the coarsest layer of hills.
+const HILL_SCALE: f32 = 48.;
+/// Tiles across one bend of the coarsest layer of caves.
+const CAVE_SCALE: f32 = 24.;
+/// How far from the middle of the noise a tile can be and still be cave, at `caves: 1`.
+const CAVE_WIDTH: f32 = 0.08;
+const OCTAVES: u32 = 4;
+
+/// Mixed into the seed so each kind of noise is different from the others.
+const SURFACE_NOISE: u64 = 0x5352_4643;
+const DIRT_NOISE: u64 = 0x4449_5254;
+const CAVE_NOISE: u64 = 0x4341_5645;
+
+/// What the generator makes. The same parameters always give the same tile types;
+/// colours are picked at random like any other new tile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldParams {
+    pub seed: u64,
+    /// Average height of the ground, as a fraction of the board's height.
+    pub ground: f32,
+    /// How far hills rise and valleys sink, as a fraction of the board's height.
+    pub hills: f32,
+    /// Rows of sand on top of the ground.
+    pub sand_depth: usize,
+    /// Rows of dirt under the sand, on average.
+    pub dirt_depth: usize,
+    /// Rows of rock along the bottom that caves never reach.
+    pub bedrock: usize,
+    /// How much of the rock is tunnelled through, from none at 0.
+    pub caves: f32,
+    /// How high lakes fill the valleys, from none at 0 to the top of the highest hill at 1.
+    pub water: f32,
+    /// Chance of a tree on each column of dry ground.
+    pub trees: f32,
+}
+
+impl WorldParams {
+    pub const DEFAULT: WorldParams = WorldParams {
+        seed: 0,
+        ground: 0.5,
+        hills: 0.12,
+        sand_depth: 2,
+        dirt_depth: 5,
+        bedrock: 2,
+        caves: 0.5,
+        water: 0.3,
+        trees: 0.06,
+    };
+}
+
+impl Default for WorldParams {
+    fn default() -> Self {
+        WorldParams::DEFAULT
+    }
+}
+
+/// Scrambles `value` so that nearby inputs give unrelated outputs.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// A number from 0 to 1 that only depends on its arguments.
+fn hash(seed: u64, x: i64, y: i64) -> f32 {
+    let hash = mix(seed
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f));
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Random values at whole coordinates, smoothly blended in between.
+fn value_noise(seed: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let smooth = |t: f32| t * t * (3. - 2. * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    lerp(
+        lerp(hash(seed, x0, y0), hash(seed, x0 + 1, y0), tx),
+        lerp(hash(seed, x0, y0 + 1), hash(seed, x0 + 1, y0 + 1), tx),
+        ty,
+    )
+}
+
+/// Layers of `value_noise`, each twice as fine and half as strong as the last, so
+/// there's detail at every size. Still from 0 to 1.
+fn fractal_noise(seed: u64, x: f32, y: f32) -> f32 {
+    let (mut total, mut strength, mut scale, mut max) = (0., 1., 1., 0.);
+    for octave in 0..OCTAVES {
+        total += value_noise(mix(seed ^ octave as u64), x * scale, y * scale) * strength;
+        max += strength;
+        strength *= 0.5;
+        scale *= 2.;
+    }
+    total / max
+}
+
+/// Builds a walled board of terrain: rock with sand and dirt on top, caves through the
+/// rock, lakes in the valleys and trees on dry ground.
+///
+/// Everything but walls falls, so each cave's roof is a wall, and trees are shaped so
+/// every column of wood stands on the ground.
+pub fn generate(width: usize, height: usize, params: &WorldParams) -> Board {
+    let noise_seed = |noise: u64| mix(params.seed ^ noise);
+    let mut tiles = vec![vec![TileType::None; height]; width];
+    let top = height.saturating_sub(2).max(1);
+
+    // The highest ground tile in each column.
+    let surface: Vec<usize> = (0..width)
+        .map(|x| {
+            let noise =
+                fractal_noise(noise_seed(SURFACE_NOISE), x as f32 / HILL_SCALE, 0.) * 2. - 1.;
+            let y = (params.ground + noise * params.hills) * height as f32;
+            (y.round().max(1.) as usize).min(top)
+        })
+        .collect();
+
+    for (x, column) in tiles.iter_mut().enumerate() {
+        let dirt = fractal_noise(noise_seed(DIRT_NOISE), x as f32 / HILL_SCALE, 0.) + 0.5;
+        let dirt_depth = (params.dirt_depth as f32 * dirt).round() as usize;
+
+        for (y, tile) in column.iter_mut().enumerate().take(surface[x] + 1) {
+            let depth = surface[x] - y;
+            *tile = if depth < params.sand_depth {
+                TileType::Sand
+            } else if depth < params.sand_depth + dirt_depth {
+                TileType::Dirt
+            } else {
+                TileType::Rock
+            };
+
+            // A layer of rock is left under the dirt so it never falls into a cave.
+            let underground = depth > params.sand_depth + dirt_depth;
+            if underground && y > params.bedrock && params.caves > 0. {
+                let noise = fractal_noise(
+                    noise_seed(CAVE_NOISE),
+                    x as f32 / CAVE_SCALE,
+                    y as f32 / CAVE_SCALE,
+                );
+                if (noise - 0.5).abs() < params.caves * CAVE_WIDTH {
+                    *tile = TileType::None;
+                }
+            }
+        }
+
+        for y in 1..column.len() {
+            if column[y - 1] == TileType::None && column[y] == TileType::Rock {
+                column[y] = TileType::Wall;
+            }
+        }
+    }
+
+    // Lakes fill every valley up to the same level.
+    let (lowest, highest) = (
+        *surface.iter().min().unwrap(),
+        *surface.iter().max().unwrap(),
+    );
+    let level = lowest + (params.water * (highest - lowest) as f32).round() as usize;
+    for (x, column) in tiles.iter_mut().enumerate() {
+        for tile in column.iter_mut().take(level + 1).skip(surface[x] + 1) {
+            *tile = TileType::Water;
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let mut x = 2;
+    while x + 2 < width {
+        let planted = rng.gen::<f32>() < params.trees;
+        let tall = rng.gen_range(4..=8);
+        if !planted || surface[x] <= level {
+            x += 1;
+            continue;
+        }
+
+        // Narrower towards the top, like a fir.
+        for dx in -1isize..=1 {
+            let column_x = (x as isize + dx) as usize;
+            let crown = (surface[x] + tall - 2 * dx.unsigned_abs()).min(top);
+            let ground = surface[column_x];
+            for tile in tiles[column_x].iter_mut().take(crown + 1).skip(ground + 1) {
+                if *tile == TileType::None {
+                    *tile = TileType::Wood;
+                }
+            }
+        }
+        x += 4;
+    }
+
+    let mut board = Board::from_tile_types(tiles);
+    for x in 0..width {
+        for y in 0..height {
+            if y == 0 || y == height - 1 || x == 0 || x == width - 1 {
+                board.tiles[x][y] = Tile::from_type(TileType::Wall);
+            }
+        }
+    }
+    board
+}
+
+/// Runs `particles generate`.
+pub fn run(args: &GenerateArgs) -> Result<(), String> {
+    let out = &args.out;
+    let board = generate(args.width, args.height, &args.params);
+    board
+        .save_to_file(out)
+        .map_err(|err| format!("Couldn't write {}: {}", out.display(), err))?;
+    println!(
+        "Generated a {}x{} board with seed {} to {}.",
+        args.width,
+        args.height,
+        args.params.seed,
+        out.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::WORLDS_PATH;
+    use std::{env, fs, path::PathBuf};
+
+    /// A generated board kept in `worlds/` to check generation doesn't change.
+    struct World {
+        name: &'static str,
+        width: usize,
+        height: usize,
+        params: WorldParams,
+    }
+
+    const WORLDS: &[World] = &[
+        World {
+            name: "default",
+            width: 120,
+            height: 60,
+            params: WorldParams::DEFAULT,
+        },
+        World {
+            name: "seed_7",
+            width: 120,
+            height: 60,
+            params: WorldParams {
+                seed: 7,
+                ..WorldParams::DEFAULT
+            },
+        },
+        World {
+            name: "caves",
+            width: 80,
+            height: 80,
+            params: WorldParams {
+                seed: 3,
+                ground: 0.8,
+                caves: 1.,
+                water: 0.,
+                ..WorldParams::DEFAULT
+            },
+        },
+        World {
+            name: "flooded",
+            width: 100,
+            height: 40,
+            params: WorldParams {
+                seed: 12,
+                hills: 0.3,
+                water: 0.8,
+                trees: 0.5,
+                ..WorldParams::DEFAULT
+            },
+        },
+        World {
+            name: "tiny",
+            width: 3,
+            height: 3,
+            params: WorldParams::DEFAULT,
+        },
+    ];
+
+    impl World {
+        fn expected_path(&self) -> PathBuf {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join(WORLDS_PATH)
+                .join(format!("{}.expected.txt", self.name))
+        }
+    }
+
+    /// Checks every world comes out the same twice in a row and the same as the last
+    /// time it was blessed. Run with `BLESS=1` to overwrite the expected boards.
+    #[test]
+    fn worlds_are_reproducible() {
+        let bless = env::var_os("BLESS").is_some();
+        let mut failed = Vec::new();
+
+        for world in WORLDS.iter() {
+            let board = generate(world.width, world.height, &world.params);
+            let again = generate(world.width, world.height, &world.params);
+            assert_eq!(
+                board.tile_types(),
+                again.tile_types(),
+                "{} came out differently the second time",
+                world.name
+            );
+
+            let actual = board.to_ascii();
+            if bless {
+                fs::write(world.expected_path(), &actual).unwrap();
+                println!("blessed {}", world.name);
+                continue;
+            }
+
+            let expected = Board::load_from_file(&world.expected_path())
+                .unwrap()
+                .to_ascii();
+            if expected != actual {
+                println!(
+                    "FAILED  {} doesn't match {}",
+                    world.name,
+                    world.expected_path().display()
+                );
+                failed.push(world.name);
+            }
+        }
+
+        assert!(
+            failed.is_empty(),
+            "{} of {} worlds failed: {}, rerun with BLESS=1 if that's intended",
+            failed.len(),
+            WORLDS.len(),
+            failed.join(", ")
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    io::{self, Seek, SeekFrom, Write},
+};
+
+/// The largest code LZW in a GIF can use.
+const MAX_CODES: u16 = 4096;
+
+/// Writes an animated GIF that loops forever, one frame at a time. The trailer is
+/// written after every frame, so whatever has been written so far is a complete file
+/// even if the program never gets to `finish`.
+pub struct GifWriter<W: Write + Seek> {
+    writer: W,
+    width: u16,
+    height: u16,
+    /// How long each frame is shown, in hundredths of a second.
+    delay: u16,
+}
+
+impl<W: Write + Seek> GifWriter<W> {
+    pub fn new(mut writer: W, width: u16, height: u16, delay: u16) -> io::Result<Self> {
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        // No global colour table, every frame brings its own.
+        writer.write_all(&[0, 0, 0])?;
+        // Loop forever.
+        writer.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        writer.write_all(&[0x3b])?;
+        writer.flush()?;
+
+        Ok(GifWriter {
+            writer,
+            width,
+            height,
+            delay,
+        })
+    }
+
+    /// Adds a frame of `width * height` pixels, row by row from the top left. Frames
+    /// with more than 256 colours lose precision until they fit.
+    pub fn write_frame(&mut self, pixels: &[[u8; 3]]) -> io::Result<()> {
+        debug_assert_eq!(pixels.len(), self.width as usize * self.height as usize);
+        let (palette, indices) = quantize(pixels);
+
+        // Palettes are a power of two long, at least 2.
+        let bits = (palette.len().max(2) as u32)
+            .next_power_of_two()
+            .trailing_zeros();
+
+        // Over the trailer left by the last frame.
+        self.writer.seek(SeekFrom::End(-1))?;
+
+        let [delay_low, delay_high] = self.delay.to_le_bytes();
+        self.writer
+            .write_all(&[0x21, 0xf9, 0x04, 0x00, delay_low, delay_high, 0x00, 0x00])?;
+
+        self.writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.writer.write_all(&self.width.to_le_bytes())?;
+        self.writer.write_all(&self.height.to_le_bytes())?;
+        self.writer.write_all(&[0x80 | (bits as u8 - 1)])?;
+        for index in 0..1 << bits {
+            let color = palette.get(index).copied().unwrap_or_default();
+            self.writer.write_all(&color)?;
+        }
+
+        let min_code_size = bits.max(2) as u8;
+        self.writer.write_all(&[min_code_size])?;
+        for block in lzw(&indices, min_code_size).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0, 0x3b])?;
+        self.writer.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Picks a palette of at most 256 colours, dropping low bits from every channel until
+/// the frame fits, and returns it with each pixel's index into it.
+fn quantize(pixels: &[[u8; 3]]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    for shift in 0..8 {
+        let reduce = |color: [u8; 3]| color.map(|channel| channel >> shift << shift);
+        let mut palette = Vec::new();
+        let mut lookup = HashMap::new();
+        let indices: Option<Vec<u8>> = pixels
+            .iter()
+            .map(|pixel| {
+                let color = reduce(*pixel);
+                if let Some(index) = lookup.get(&color) {
+                    return Some(*index);
+                }
+                let index = u8::try_from(palette.len()).ok()?;
+                palette.push(color);
+                lookup.insert(color, index);
+                Some(index)
+            })
+            .collect();
+
+        if let Some(indices) = indices {
+            return (palette, indices);
+        }
+    }
+    unreachable!("one bit per channel is only 8 colours")
+}
+
+/// Packs codes into bytes least significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Compresses palette indices the way GIF image data is.
+fn lzw(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut output = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = min_code_size + 1;
+    output.write(clear, size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        output.write(end, size);
+        return output.finish();
+    };
+    let mut prefix = first as u16;
+
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        output.write(prefix, size);
+        if next < MAX_CODES {
+            table.insert((prefix, index), next);
+            next += 1;
+            // The decoder adds its entries a code behind, so it grows a code later.
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        } else {
+            output.write(clear, size);
+            table.clear();
+            next = end + 1;
+            size = min_code_size + 1;
+        }
+        prefix = index as u16;
+    }
+
+    output.write(prefix, size);
+    // The decoder adds an entry for the last code before it reads the end code.
+    if next < MAX_CODES && next + 1 > 1 << size && size < 12 {
+        size += 1;
+    }
+    output.write(end, size);
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::io::Cursor;
+
+    fn encode(width: u16, height: u16, frames: &[Vec<[u8; 3]>]) -> Vec<u8> {
+        let mut gif = GifWriter::new(Cursor::new(Vec::new()), width, height, 5).unwrap();
+        for frame in frames {
+            gif.write_frame(frame).unwrap();
+        }
+        gif.finish().unwrap().into_inner()
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<Vec<[u8; 3]>> {
+        GifDecoder::new(Cursor::new(bytes))
+            .unwrap()
+            .into_frames()
+            .map(|frame| {
+                frame
+                    .unwrap()
+                    .into_buffer()
+                    .pixels()
+                    .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn random_frame(pixels: usize, colors: &[[u8; 3]], rng: &mut StdRng) -> Vec<[u8; 3]> {
+        (0..pixels)
+            .map(|_| colors[rng.gen_range(0..colors.len())])
+            .collect()
+    }
+
+    #[test]
+    fn frames_decode_as_written() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let colors: Vec<[u8; 3]> = (0..5).map(|_| rng.gen()).collect();
+        let frames: Vec<_> = (0..3)
+            .map(|_| random_frame(12 * 7, &colors, &mut rng))
+            .collect();
+
+        assert_eq!(decode(&encode(12, 7, &frames)), frames);
+    }
+
+    #[test]
+    fn one_pixel_frame() {
+        let frames = vec![vec![[10, 20, 30]]];
+        assert_eq!(decode(&encode(1, 1, &frames)), frames);
+    }
+
+    #[test]
+    fn long_frames_fill_the_code_table() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // Random pixels rarely repeat a run, so codes run out and the table is cleared
+        // several times, with the smallest and the largest code sizes.
+        let two = [[0, 0, 0], [255, 255, 255]];
+        let many: Vec<[u8; 3]> = (0..=255).map(|value| [value, 0, 255 - value]).collect();
+        let frames = vec![
+            random_frame(300 * 200, &two, &mut rng),
+            random_frame(300 * 200, &many, &mut rng),
+        ];
+
+        let indices = quantize(&frames[1]).1;
+        // No code is over 12 bits, so this holds more codes than the table has room for.
+        let codes = lzw(&indices, 8);
+        assert!(codes.len() * 8 > MAX_CODES as usize * 12);
+
+        assert_eq!(decode(&encode(300, 200, &frames)), frames);
+    }
+
+    #[test]
+    fn frames_with_too_many_colours_are_quantized() {
+        let frame: Vec<[u8; 3]> = (0..64 * 64)
+            .map(|index| [(index % 64 * 4) as u8, (index / 64 * 4) as u8, 128])
+            .collect();
+
+        let (palette, indices) = quantize(&frame);
+        assert!(palette.len() <= 256);
+        let expected: Vec<[u8; 3]> = indices
+            .iter()
+            .map(|index| palette[*index as usize])
+            .collect();
+        for (pixel, quantized) in frame.iter().zip(expected.iter()) {
+            for (channel, reduced) in pixel.iter().zip(quantized.iter()) {
+                assert!(reduced <= channel && channel - reduced < 32);
+            }
+        }
+
+        assert_eq!(decode(&encode(64, 64, &[frame])), [expected]);
+    }
+
+    #[test]
+    fn finish_leaves_one_trailer() {
+        let empty = encode(4, 4, &[]);
+        assert_eq!(empty.iter().filter(|byte| **byte == 0x3b).count(), 1);
+        assert_eq!(empty.last(), Some(&0x3b));
+
+        let frames = vec![vec![[1, 2, 3]; 16]; 3];
+        let bytes = encode(4, 4, &frames);
+        assert_eq!(bytes[bytes.len() - 2..], [0, 0x3b]);
+        // Each frame writes over the trailer left by the one before, so every frame
+        // adds the same number of bytes.
+        let one = encode(4, 4, &frames[..1]);
+        assert_eq!(bytes.len() - one.len(), 2 * (one.len() - empty.len()));
+        assert_eq!(decode(&bytes), frames);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{
+    board::Board,
+    cli::ImportArgs,
+    consts::COLOR_VARIATION,
+    settings::BOARD_SIZE_RANGE,
+    tile::{TileType, MATERIALS},
+};
+use bevy::prelude::*;
+use image::{imageops::FilterType, RgbaImage};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+/// How far, as a distance in RGB, a pixel can be from a material's colour and still
+/// count as that material.
+pub const DEFAULT_TOLERANCE: f32 = 64.;
+
+/// Unmapped colours listed in an error before the rest are summarised.
+const MAX_REPORTED: usize = 8;
+
+/// Pixels more transparent than this are empty tiles.
+const MIN_ALPHA: u8 = 128;
+
+/// Whether a file should be read as an image rather than a board file.
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        ["png", "ppm", "pnm"]
+            .iter()
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    })
+}
+
+/// Which material each pixel colour becomes.
+pub struct Palette {
+    /// Colours from a mapping file, only matched exactly.
+    exact: Vec<([u8; 3], TileType)>,
+    /// Every material's base colour, matched within `tolerance`.
+    nearest: Vec<([u8; 3], TileType)>,
+    tolerance: f32,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            exact: Vec::new(),
+            nearest: MATERIALS
+                .iter()
+                .map(|mat| (rgb(mat.tile_type.base_color()), mat.tile_type))
+                .collect(),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    let [r, g, b, _] = color.as_rgba_u8();
+    [r, g, b]
+}
+
+/// Distance in RGB from `palette` to `color`, after taking off as much of a shift to
+/// all three channels as tiles' colours are varied by, so a darker or lighter tile
+/// still matches its own material rather than a neighbouring one.
+fn distance(palette: [u8; 3], color: [u8; 3]) -> f32 {
+    let difference: Vec<f32> = color
+        .iter()
+        .zip(palette.iter())
+        .map(|(color, palette)| *color as f32 - *palette as f32)
+        .collect();
+    let variation = COLOR_VARIATION * 255.;
+    let shift = (difference.iter().sum::<f32>() / 3.).clamp(-variation, variation);
+
+    difference
+        .iter()
+        .map(|difference| (difference - shift).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+impl Palette {
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Adds the colours from a mapping file, a JSON object of hex colours to tile types:
+    ///
+    /// ```text
+    /// {"#ff0000": "Fire", "#0000ff": "Water", "ffffff": "None"}
+    /// ```
+    pub fn load_mapping(&mut self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+        let mapping: BTreeMap<String, TileType> = serde_json::from_str(&contents)
+            .map_err(|err| format!("{} isn't a colour mapping: {}", path.display(), err))?;
+
+        for (hex, tile_type) in mapping {
+            let color = Color::hex(&hex)
+                .map_err(|_| format!("{}: '{}' isn't a hex colour", path.display(), hex))?;
+            self.exact.push((rgb(color), tile_type));
+        }
+        Ok(())
+    }
+
+    pub fn tile_type(&self, color: [u8; 3]) -> Option<TileType> {
+        if let Some((_, tile_type)) = self.exact.iter().find(|(exact, _)| *exact == color) {
+            return Some(*tile_type);
+        }
+
+        self.nearest
+            .iter()
+            .map(|(palette, tile_type)| (distance(*palette, color), *tile_type))
+            .filter(|(distance, _)| *distance <= self.tolerance)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, tile_type)| tile_type)
+    }
+}
+
+/// A colour no material matched, how many pixels had it and where the first one was,
+/// in board coordinates.
+#[derive(Clone, Debug)]
+pub struct Unmapped {
+    pub color: [u8; 3],
+    pub pixels: usize,
+    pub first: (usize, usize),
+}
+
+/// Turns an image into a board, one tile per pixel with the top row of the image at
+/// the top of the board. Pixels no material matches become `fill`, and are returned.
+pub fn board_from_image(
+    image: &RgbaImage,
+    palette: &Palette,
+    fill: TileType,
+) -> (Board, Vec<Unmapped>) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut tiles = vec![vec![TileType::None; height]; width];
+    let mut unmapped: HashMap<[u8; 3], Unmapped> = HashMap::new();
+
+    for (pixel_x, pixel_y, pixel) in image.enumerate_pixels() {
+        let (x, y) = (pixel_x as usize, height - 1 - pixel_y as usize);
+        let [r, g, b, a] = pixel.0;
+        if a < MIN_ALPHA {
+            continue;
+        }
+
+        match palette.tile_type([r, g, b]) {
+            Some(tile_type) => tiles[x][y] = tile_type,
+            None => {
+                tiles[x][y] = fill;
+                unmapped
+                    .entry([r, g, b])
+                    .or_insert(Unmapped {
+                        color: [r, g, b],
+                        pixels: 0,
+                        first: (x, y),
+                    })
+                    .pixels += 1;
+            }
+        }
+    }
+
+    // Ties keep the order the image was read in, top row first.
+    let mut unmapped: Vec<Unmapped> = unmapped.into_values().collect();
+    unmapped.sort_by_key(|entry| (Reverse(entry.pixels), Reverse(entry.first.1), entry.first.0));
+    (Board::from_tile_types(tiles), unmapped)
+}
+
+/// Describes the unmapped colours for an error message, most common first.
+pub fn report(unmapped: &[Unmapped]) -> String {
+    let pixels: usize = unmapped.iter().map(|entry| entry.pixels).sum();
+    let mut report = format!(
+        "{} pixels in {} colours don't match any material:",
+        pixels,
+        unmapped.len()
+    );
+    for entry in unmapped.iter().take(MAX_REPORTED) {
+        let [r, g, b] = entry.color;
+        let _ = write!(
+            report,
+            "\n  #{:02x}{:02x}{:02x}: {} pixels, first at ({}, {})",
+            r, g, b, entry.pixels, entry.first.0, entry.first.1
+        );
+    }
+    if unmapped.len() > MAX_REPORTED {
+        let _ = write!(report, "\n  and {} more", unmapped.len() - MAX_REPORTED);
+    }
+    report
+}
+
+/// Reads a PNG or PPM, resized to `size` if given.
+pub fn load_image(path: &Path, size: Option<(usize, usize)>) -> Result<RgbaImage, String> {
+    let image = image::open(path)
+        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?
+        .to_rgba8();
+
+    Ok(match size {
+        // Nearest so no colours are made up between two materials.
+        Some((width, height)) => {
+            image::imageops::resize(&image, width as u32, height as u32, FilterType::Nearest)
+        }
+        None => image,
+    })
+}
+
+/// Loads an image at its own size with the default palette, failing if any colour
+/// doesn't match a material or the image is too small or too big for a board.
+pub fn load_board(path: &Path) -> Result<Board, String> {
+    let (width, height) = image::image_dimensions(path)
+        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+    if !BOARD_SIZE_RANGE.contains(&(width as usize))
+        || !BOARD_SIZE_RANGE.contains(&(height as usize))
+    {
+        return Err(format!(
+            "{} is {}x{} pixels, boards must be between {} and {} tiles across and high; \
+             resize it with particles import --width and --height",
+            path.display(),
+            width,
+            height,
+            BOARD_SIZE_RANGE.start(),
+            BOARD_SIZE_RANGE.end()
+        ));
+    }
+
+    let image = load_image(path, None)?;
+    let (board, unmapped) = board_from_image(&image, &Palette::default(), TileType::None);
+    if !unmapped.is_empty() {
+        return Err(format!("{}: {}", path.display(), report(&unmapped)));
+    }
+    Ok(board)
+}
+
+/// Runs `particles import`.
+pub fn run(args: &ImportArgs) -> Result<(), String> {
+    let mut palette = Palette::default().with_tolerance(args.tolerance);
+    if let Some(mapping) = &args.mapping {
+        palette.load_mapping(mapping)?;
+    }
+
+    let image = load_image(&args.image, args.size)?;
+    let fill = args.unmapped.unwrap_or(TileType::None);
+    let (board, unmapped) = board_from_image(&image, &palette, fill);
+
+    if !unmapped.is_empty() {
+        if args.unmapped.is_none() {
+            return Err(format!(
+                "{}\n\nAdd them to a --mapping file, raise --tolerance or fill them with --unmapped <MATERIAL>.",
+                report(&unmapped)
+            ));
+        }
+        println!("{}\nFilled them with {:?}.", report(&unmapped), fill);
+    }
+
+    board
+        .save_to_file(&args.out)
+        .map_err(|err| format!("Couldn't write {}: {}", args.out.display(), err))?;
+    println!(
+        "Imported a {}x{} board to {}.",
+        board.width(),
+        board.height(),
+        args.out.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn unmapped_colours_are_counted_most_common_first() {
+        let sand = rgb(TileType::Sand.base_color());
+        let mut image = RgbaImage::from_pixel(4, 3, Rgba([sand[0], sand[1], sand[2], 255]));
+        image.put_pixel(1, 0, Rgba([255, 0, 255, 255]));
+        image.put_pixel(3, 2, Rgba([0, 255, 255, 255]));
+        image.put_pixel(0, 2, Rgba([0, 255, 255, 255]));
+
+        let (board, unmapped) = board_from_image(&image, &Palette::default(), TileType::Wall);
+
+        assert_eq!(board.get(1, 2).unwrap().tile_type, TileType::Wall);
+        assert_eq!(board.get(2, 2).unwrap().tile_type, TileType::Sand);
+        let found: Vec<_> = unmapped
+            .iter()
+            .map(|entry| (entry.color, entry.pixels, entry.first))
+            .collect();
+        assert_eq!(
+            found,
+            [([0, 255, 255], 2, (0, 0)), ([255, 0, 255], 1, (1, 2))]
+        );
+    }
+
+    #[test]
+    fn images_too_big_for_a_board_are_refused() {
+        let dir = std::env::temp_dir().join(format!("particles-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wide.png");
+        let width = *BOARD_SIZE_RANGE.end() as u32 + 1;
+        RgbaImage::new(width, 4).save(&path).unwrap();
+
+        let err = load_board(&path).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(err.contains(&format!("is {}x4 pixels", width)), "{}", err);
+    }
+}
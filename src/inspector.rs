@@ -0,0 +1,83 @@
+use crate::{
+    board::Board,
+    controls::{cursor_tile, pointer_over_ui, ui_visible},
+    settings::Settings,
+};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, inspector_window.run_if(ui_visible));
+    }
+}
+
+fn flag(ui: &mut egui::Ui, name: &str, value: bool) {
+    ui.label(name);
+    ui.label(if value { "yes" } else { "no" });
+    ui.end_row();
+}
+
+/// Shows everything about the tile under the cursor, refreshed every frame.
+fn inspector_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    board: Res<Board>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    let window = windows.single();
+    let (camera, camera_transform) = cameras.single();
+    let hovered = cursor_tile(window, camera, camera_transform)
+        .filter(|_| !pointer_over_ui(&mut contexts))
+        .and_then(|(x, y)| board.get(x, y).map(|tile| (x, y, tile)));
+
+    egui::Window::new("Inspector")
+        .open(&mut settings.ui.inspector)
+        .show(contexts.ctx_mut(), |ui| {
+            let Some((x, y, tile)) = hovered else {
+                ui.label("Hover over the board to inspect a tile.");
+                return;
+            };
+
+            egui::Grid::new("inspector").striped(true).show(ui, |ui| {
+                ui.label("Position");
+                ui.label(format!("({}, {})", x, y));
+                ui.end_row();
+
+                ui.label("Type");
+                ui.label(format!("{:?}", tile.tile_type));
+                ui.end_row();
+
+                flag(ui, "Solid", tile.solid);
+                flag(ui, "Flows", tile.flows);
+                flag(ui, "Gravity", tile.gravity);
+                flag(ui, "Gas", tile.gas);
+                flag(ui, "Piles", tile.piles);
+                flag(ui, "Flammable", tile.flammable);
+
+                ui.label("Strength");
+                ui.label(match tile.strength {
+                    Some(strength) => strength.to_string(),
+                    None => "-".to_string(),
+                });
+                ui.end_row();
+
+                let [r, g, b, a] = tile.color.as_rgba_u8();
+                ui.label("Color");
+                ui.horizontal(|ui| {
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::vec2(12., 12.), egui::Sense::hover());
+                    ui.painter().rect_filled(
+                        rect,
+                        0.,
+                        egui::Color32::from_rgba_unmultiplied(r, g, b, a),
+                    );
+                    ui.label(format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a));
+                });
+                ui.end_row();
+            });
+        });
+}
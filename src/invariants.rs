@@ -0,0 +1,191 @@
+use crate::{board::Board, physics::Rule, stats::BoardStats, tile::TileType, tile::MATERIALS};
+use std::fmt;
+
+/// The first thing a rule did that it shouldn't have.
+#[derive(Clone, Debug)]
+pub struct Violation {
+    pub rule: Rule,
+    /// The tile the rule was applied to.
+    pub x: usize,
+    pub y: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at ({}, {}): {}",
+            self.rule, self.x, self.y, self.message
+        )
+    }
+}
+
+/// Checks what applying `rule` to the tile at `(x, y)` did to the board:
+///
+/// - the board keeps its size, and only the tile and its eight neighbours change
+/// - every tile marked dirty is on the board
+/// - walls never move, appear or disappear
+/// - rules that move tiles keep the count of every tile type
+pub fn check(
+    rule: Rule,
+    x: usize,
+    y: usize,
+    before: &Board,
+    after: &Board,
+) -> Result<(), Violation> {
+    let violation = |message: String| Violation {
+        rule,
+        x,
+        y,
+        message,
+    };
+
+    if before.width() != after.width()
+        || after
+            .tiles
+            .iter()
+            .any(|column| column.len() != before.height())
+    {
+        return Err(violation(format!(
+            "board changed size from {}x{}",
+            before.width(),
+            before.height()
+        )));
+    }
+
+    if let Some((tile_x, tile_y)) = after
+        .dirty_tiles
+        .iter()
+        .find(|(tile_x, tile_y)| *tile_x >= after.width() || *tile_y >= after.height())
+    {
+        return Err(violation(format!(
+            "marked ({}, {}) dirty, which is off the board",
+            tile_x, tile_y
+        )));
+    }
+
+    for (tile_x, (column_before, column_after)) in
+        before.tiles.iter().zip(after.tiles.iter()).enumerate()
+    {
+        for (tile_y, (tile_before, tile_after)) in
+            column_before.iter().zip(column_after.iter()).enumerate()
+        {
+            if tile_before.tile_type == tile_after.tile_type {
+                continue;
+            }
+
+            if tile_before.tile_type == TileType::Wall || tile_after.tile_type == TileType::Wall {
+                return Err(violation(format!(
+                    "wall changed at ({}, {}), {:?} became {:?}",
+                    tile_x, tile_y, tile_before.tile_type, tile_after.tile_type
+                )));
+            }
+
+            if tile_x.abs_diff(x) > 1 || tile_y.abs_diff(y) > 1 {
+                return Err(violation(format!(
+                    "changed ({}, {}) which isn't next to it",
+                    tile_x, tile_y
+                )));
+            }
+        }
+    }
+
+    if rule.conserves_tiles() {
+        let stats_before = BoardStats::new(before, None);
+        let stats_after = BoardStats::new(after, None);
+
+        for mat in MATERIALS.iter() {
+            let (count_before, count_after) = (
+                stats_before.count(mat.tile_type),
+                stats_after.count(mat.tile_type),
+            );
+            if count_before != count_after {
+                return Err(violation(format!(
+                    "{:?} went from {} tiles to {}",
+                    mat.tile_type, count_before, count_after
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{physics::step_checked, tile::Tile};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn every_material_steps_cleanly() {
+        let mut board = Board::from_ascii(
+            "
+            ##########
+            #ss~~ff..#
+            #rr.aa.dd#
+            #..ww~~ss#
+            #.f.aw.~.#
+            #dd..rr..#
+            ##########
+            ",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for tick in 1..=50 {
+            if let Err(violation) = step_checked(&mut board, &mut rng) {
+                panic!("tick {}: {}", tick, violation);
+            }
+            board.clear_dirty_tiles();
+        }
+    }
+
+    #[test]
+    fn walls_must_not_change() {
+        let before = Board::from_ascii("#.\n..").unwrap();
+        let mut after = before.clone();
+        after.tiles[0][1] = Tile::from_type(TileType::Sand);
+
+        let violation = check(Rule::Gravity, 0, 1, &before, &after).unwrap_err();
+        assert_eq!(
+            violation.message,
+            "wall changed at (0, 1), Wall became Sand"
+        );
+    }
+
+    #[test]
+    fn only_neighbours_may_change() {
+        let before = Board::from_ascii("....\n....").unwrap();
+        let mut after = before.clone();
+        after.tiles[3][0] = Tile::from_type(TileType::Sand);
+
+        let violation = check(Rule::FireSpread, 0, 0, &before, &after).unwrap_err();
+        assert_eq!(violation.message, "changed (3, 0) which isn't next to it");
+    }
+
+    #[test]
+    fn moving_rules_keep_tile_counts() {
+        let before = Board::from_ascii("s.\n..").unwrap();
+        let mut after = before.clone();
+        after.tiles[0][1] = Tile::from_type(TileType::None);
+
+        let violation = check(Rule::Gravity, 0, 1, &before, &after).unwrap_err();
+        assert_eq!(violation.message, "None went from 3 tiles to 4");
+        check(Rule::Acid, 0, 1, &before, &after).unwrap();
+    }
+
+    #[test]
+    fn dirty_tiles_must_be_on_the_board() {
+        let before = Board::from_ascii("..\n..").unwrap();
+        let mut after = before.clone();
+        after.dirty_tiles.push((2, 0));
+
+        let violation = check(Rule::Flow, 1, 0, &before, &after).unwrap_err();
+        assert_eq!(
+            violation.message,
+            "marked (2, 0) dirty, which is off the board"
+        );
+    }
+}
@@ -1,58 +1,176 @@
 use bevy_egui::EguiPlugin;
-use consts::{HEIGHT, TILE_HEIGHT, TILE_SIZE, TILE_WIDTH, WIDTH};
+use consts::{HEIGHT, WIDTH};
+mod ascii;
+mod autosave;
+mod bench;
+mod binary;
+mod bindings;
 mod board;
+mod camera;
+mod cli;
+mod clipboard;
 mod consts;
 mod controls;
+mod export;
+#[cfg(test)]
+mod formats;
+mod fuzz;
+mod generate;
+mod gif;
+mod import;
+mod inspector;
+mod invariants;
+mod multiplayer;
 mod physics;
+mod remote;
+mod replay;
+#[cfg(test)]
+mod scenarios;
+mod settings;
+mod sim;
+mod stamps;
+mod stats;
 mod tile;
+mod timeline;
 use crate::physics::PhysicsPlugin;
+use autosave::AutosavePlugin;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
-use bevy::{prelude::*, render::camera::ScalingMode};
+use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use board::BoardPlugin;
+use bindings::BindingsPlugin;
+use board::{Board, BoardPlugin};
+use camera::CameraPlugin;
+use cli::{Cli, Command};
+use clipboard::ClipboardPlugin;
 use controls::ControlsPlugin;
+use export::{FramesPlugin, GifPlugin};
+use inspector::InspectorPlugin;
+use multiplayer::{ClientPlugin, ServerPlugin};
+use remote::RemotePlugin;
+use replay::ReplayPlugin;
+use settings::SettingsPlugin;
+use stamps::StampsPlugin;
+use stats::StatsPlugin;
+use timeline::TimelinePlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(
+    let cli = match Cli::parse(std::env::args().skip(1)).and_then(|cli| {
+        cli.validate()?;
+        Ok(cli)
+    }) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("error: {}\n\nRun with --help to see the options.", err);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(help) = cli.help {
+        print!("{}", help);
+        return;
+    }
+
+    if let Some(command) = &cli.command {
+        let result = match command {
+            Command::Sim(args) => sim::run(args),
+            Command::Fuzz(args) => fuzz::run(args),
+            Command::Bench(args) => bench::run(args),
+            Command::Replay(args) => replay::run(args),
+            Command::Import(args) => import::run(args),
+            Command::Generate(args) => generate::run(args),
+        };
+        if let Err(err) = result {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let board = cli.load.as_ref().map(|path| {
+        Board::load_from_file(path).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(2);
+        })
+    });
+
+    let mut app = App::new();
+
+    if cli.headless {
+        app.add_plugins(MinimalPlugins);
+    } else {
+        let (width, height) = cli.window.unwrap_or((WIDTH, HEIGHT));
+        app.add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
                 .set(WindowPlugin {
                     primary_window: Some(Window {
                         title: "Particles".into(),
-                        resolution: (WIDTH, HEIGHT).into(),
+                        resolution: (width, height).into(),
                         ..default()
                     }),
                     ..default()
                 }),
         )
-        .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
-        .insert_resource(Time::<Fixed>::from_hz(4.))
-        .add_plugins((
-            BoardPlugin,
+        .insert_resource(ClearColor(Color::rgb(0., 0., 0.)));
+    }
+
+    if let Some(board) = board {
+        app.insert_resource(board);
+    }
+
+    app.add_plugins((
+        SettingsPlugin { cli: cli.clone() },
+        BoardPlugin,
+        PhysicsPlugin,
+    ));
+
+    if !cli.headless {
+        app.add_plugins((
+            CameraPlugin,
             // WorldInspectorPlugin::new(),
             EguiPlugin,
+            BindingsPlugin,
             ControlsPlugin,
+            ClipboardPlugin,
+            StampsPlugin,
+            InspectorPlugin,
+            StatsPlugin,
+            ReplayPlugin,
+            TimelinePlugin,
+            AutosavePlugin,
             FrameTimeDiagnosticsPlugin,
-            PhysicsPlugin,
-        ))
-        .add_systems(Startup, setup)
-        .run();
-}
+        ));
+    }
 
-fn setup(mut commands: Commands) {
-    let mut camera = Camera2dBundle {
-        transform: Transform::from_xyz(
-            TILE_HEIGHT as f32 * TILE_SIZE / 2.,
-            TILE_WIDTH as f32 * TILE_SIZE / 2.,
-            0.,
-        )
-        .with_scale(Vec3::new(2., 2., 1.)),
-        ..Default::default()
-    };
-    camera.projection.scaling_mode = ScalingMode::AutoMin {
-        min_height: HEIGHT,
-        min_width: WIDTH,
-    };
-    commands.spawn(camera);
+    app.add_plugins(GifPlugin {
+        path: cli.gif.clone(),
+        region: cli.region,
+        every: cli.frame_every.unwrap_or(1),
+        scale: cli.scale.unwrap_or(1),
+    });
+    if let Some(dir) = &cli.frames {
+        app.add_plugins(FramesPlugin {
+            dir: dir.clone(),
+            every: cli.frame_every.unwrap_or(1),
+            scale: cli.scale.unwrap_or(1),
+        });
+    }
+    if let Some(address) = &cli.host {
+        app.add_plugins(ServerPlugin {
+            address: address.clone(),
+        });
+    }
+    if let Some(address) = &cli.connect {
+        app.add_plugins(ClientPlugin {
+            address: address.clone(),
+        });
+    }
+
+    if let Some(address) = &cli.control {
+        app.add_plugins(RemotePlugin {
+            address: address.clone(),
+        });
+    }
+
+    app.insert_resource(cli).run();
 }
@@ -0,0 +1,557 @@
+use crate::{
+    board::{Board, DrawBoard},
+    physics::Simulation,
+    settings::RADIUS_RANGE,
+    tile::{Tile, TileType},
+};
+use bevy::{prelude::*, utils::HashSet};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{io, net::SocketAddr, thread};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+use tokio_util::{
+    bytes::{Bytes, BytesMut},
+    codec::{Decoder, Encoder, LengthDelimitedCodec},
+};
+
+/// Big enough for the welcome message of the largest board the settings allow.
+const MAX_FRAME_LENGTH: usize = 64 * 1024 * 1024;
+
+/// One changed tile: `[x, y, tile_type]`.
+pub type TileChange = (usize, usize, TileType);
+
+/// A tile as the server sends it, `[tile_type, strength, [r, g, b, a]]`, so clients show
+/// exactly the server's tiles, colours included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncedTile(TileType, Option<u8>, [u8; 4]);
+
+impl From<&Tile> for SyncedTile {
+    fn from(tile: &Tile) -> Self {
+        SyncedTile(tile.tile_type, tile.strength, tile.color.as_rgba_u8())
+    }
+}
+
+impl From<SyncedTile> for Tile {
+    fn from(SyncedTile(tile_type, strength, [r, g, b, a]): SyncedTile) -> Self {
+        Tile {
+            strength,
+            color: Color::rgba_u8(r, g, b, a),
+            ..Tile::from_type(tile_type)
+        }
+    }
+}
+
+/// Messages a client sends to the server.
+///
+/// Every message in both directions is a JSON object in a frame prefixed with its
+/// length as a 4-byte big-endian integer. The `type` field says which message it is:
+///
+/// ```text
+/// {"type": "edit", "tiles": [[4, 10, "Sand"], [5, 10, "Sand"]]}
+/// {"type": "paint", "x": 4, "y": 10, "radius": 2, "tile_type": "Water"}
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Set tiles to the given types.
+    Edit { tiles: Vec<TileChange> },
+    /// Paint a circle like the brush does.
+    Paint {
+        x: usize,
+        y: usize,
+        radius: isize,
+        tile_type: TileType,
+    },
+}
+
+/// Messages the server sends to its clients. Framed the same way as `ClientMessage`:
+///
+/// ```text
+/// {"type": "welcome", "tiles": [[["Wall", null, [92, 92, 92, 255]], ...], ...]}
+/// {"type": "delta", "tiles": [[4, 9, ["Fire", 3, [231, 84, 28, 255]]]]}
+/// ```
+///
+/// `welcome` is sent on connecting, with the whole board indexed `[x][y]` like a board
+/// file. After that every frame's dirty tiles are sent as a `delta`, or the whole board
+/// as a new `welcome` if it was resized or most of it changed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        tiles: Vec<Vec<SyncedTile>>,
+    },
+    Delta {
+        tiles: Vec<(usize, usize, SyncedTile)>,
+    },
+}
+
+fn codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .max_frame_length(MAX_FRAME_LENGTH)
+        .new_codec()
+}
+
+/// Reads the next message, or `None` once the other end has closed the connection.
+async fn read_message<T: DeserializeOwned>(
+    reader: &mut (impl AsyncRead + Unpin),
+    codec: &mut LengthDelimitedCodec,
+    buffer: &mut BytesMut,
+) -> io::Result<Option<T>> {
+    loop {
+        if let Some(frame) = codec.decode(buffer)? {
+            return serde_json::from_slice(&frame)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+        if reader.read_buf(buffer).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+async fn write_message<T: Serialize>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    codec: &mut LengthDelimitedCodec,
+    message: &T,
+) -> io::Result<()> {
+    let mut frame = BytesMut::new();
+    codec.encode(Bytes::from(serde_json::to_vec(message)?), &mut frame)?;
+    writer.write_all(&frame).await
+}
+
+/// Sends everything from `outgoing` until it closes or the connection drops.
+async fn write_loop<T: Serialize>(
+    mut writer: impl AsyncWrite + Unpin,
+    mut outgoing: UnboundedReceiver<T>,
+) -> io::Result<()> {
+    let mut codec = codec();
+    while let Some(message) = outgoing.recv().await {
+        write_message(&mut writer, &mut codec, &message).await?;
+    }
+    Ok(())
+}
+
+/// Runs a tokio runtime for the networking on its own thread, next to Bevy's.
+pub fn spawn_runtime<F: std::future::Future<Output = ()> + Send + 'static>(task: F) {
+    thread::spawn(move || {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("couldn't start the network runtime")
+            .block_on(task)
+    });
+}
+
+/// Every dirty tile, once each.
+fn dirty_positions(board: &Board) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut seen = HashSet::new();
+    board
+        .dirty_tiles
+        .iter()
+        .copied()
+        .filter(move |position| seen.insert(*position))
+}
+
+/// The current type of every dirty tile, once each.
+fn dirty_changes(board: &Board) -> Vec<TileChange> {
+    dirty_positions(board)
+        .filter_map(|(x, y)| board.get(x, y).map(|tile| (x, y, tile.tile_type)))
+        .collect()
+}
+
+fn welcome(board: &Board) -> ServerMessage {
+    ServerMessage::Welcome {
+        tiles: board
+            .tiles
+            .iter()
+            .map(|column| column.iter().map(SyncedTile::from).collect())
+            .collect(),
+    }
+}
+
+/// Hosts the board: applies clients' edits and sends everyone what changed.
+pub struct ServerPlugin {
+    pub address: String,
+}
+
+enum ServerEvent {
+    Listening(SocketAddr),
+    Joined(usize, UnboundedSender<ServerMessage>),
+    Message(ClientMessage),
+    Left(usize),
+}
+
+#[derive(Resource)]
+struct Server {
+    events: UnboundedReceiver<ServerEvent>,
+    clients: Vec<(usize, UnboundedSender<ServerMessage>)>,
+    /// The address actually listened on, once it is.
+    address: Option<SocketAddr>,
+    /// The size of the board the clients last got.
+    size: (usize, usize),
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let (events_sender, events) = unbounded_channel();
+        let address = self.address.clone();
+
+        spawn_runtime(async move {
+            let listener = match TcpListener::bind(&address).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    eprintln!("error: couldn't listen on {}: {}", address, err);
+                    std::process::exit(1);
+                }
+            };
+            if let Ok(address) = listener.local_addr() {
+                println!("Hosting on {}.", address);
+                let _ = events_sender.send(ServerEvent::Listening(address));
+            }
+
+            let mut next_id = 0;
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        println!("{} joined.", peer);
+                        tokio::spawn(serve_client(stream, next_id, events_sender.clone()));
+                        next_id += 1;
+                    }
+                    Err(err) => eprintln!("Couldn't accept a connection: {}", err),
+                }
+            }
+        });
+
+        app.insert_resource(Server {
+            events,
+            clients: Vec::new(),
+            address: None,
+            size: (0, 0),
+        })
+        .add_systems(PostUpdate, server_sync.before(DrawBoard));
+    }
+}
+
+async fn serve_client(stream: TcpStream, id: usize, events: UnboundedSender<ServerEvent>) {
+    let (mut reader, writer) = stream.into_split();
+    let (outgoing, outgoing_receiver) = unbounded_channel();
+    let _ = events.send(ServerEvent::Joined(id, outgoing));
+    tokio::spawn(write_loop(writer, outgoing_receiver));
+
+    let mut codec = codec();
+    let mut buffer = BytesMut::new();
+    loop {
+        match read_message(&mut reader, &mut codec, &mut buffer).await {
+            Ok(Some(message)) => {
+                let _ = events.send(ServerEvent::Message(message));
+            }
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("Dropping client {}: {}", id, err);
+                break;
+            }
+        }
+    }
+
+    let _ = events.send(ServerEvent::Left(id));
+}
+
+/// What brings the clients up to date with `board`: its dirty tiles, or the whole board
+/// if it isn't the `size` they last got or most of it changed.
+fn sync_message(size: (usize, usize), board: &Board) -> Option<ServerMessage> {
+    if (board.width(), board.height()) != size {
+        return Some(welcome(board));
+    }
+
+    let tiles: Vec<_> = dirty_positions(board)
+        .map(|(x, y)| (x, y, SyncedTile::from(&board.tiles[x][y])))
+        .collect();
+    if tiles.is_empty() {
+        None
+    } else if tiles.len() > board.width() * board.height() / 2 {
+        Some(welcome(board))
+    } else {
+        Some(ServerMessage::Delta { tiles })
+    }
+}
+
+fn server_sync(mut board: ResMut<Board>, mut server: ResMut<Server>) {
+    while let Ok(event) = server.events.try_recv() {
+        match event {
+            ServerEvent::Listening(address) => server.address = Some(address),
+            ServerEvent::Joined(id, client) => {
+                if client.send(welcome(&board)).is_ok() {
+                    server.clients.push((id, client));
+                }
+            }
+            ServerEvent::Left(id) => server.clients.retain(|(client, _)| *client != id),
+            // Out of bounds edits are ignored, a client's board might be a different size
+            // for a moment after the server's changes.
+            ServerEvent::Message(ClientMessage::Edit { tiles }) => {
+                for (x, y, tile_type) in tiles {
+                    let _ = board.set(x, y, Tile::from_type(tile_type));
+                }
+            }
+            ServerEvent::Message(ClientMessage::Paint {
+                x,
+                y,
+                radius,
+                tile_type,
+            }) => {
+                // The same limit as the brush, a huge radius would stall every frame.
+                let radius = radius.clamp(*RADIUS_RANGE.start(), *RADIUS_RANGE.end());
+                let _ = board.set_radius(x, y, Tile::from_type(tile_type), radius);
+            }
+        }
+    }
+
+    let Some(message) = sync_message(server.size, &board) else {
+        return;
+    };
+    server.size = (board.width(), board.height());
+    server
+        .clients
+        .retain(|(_, client)| client.send(message.clone()).is_ok());
+}
+
+/// Mirrors a board hosted by a server, sending local edits to it.
+pub struct ClientPlugin {
+    pub address: String,
+}
+
+enum ClientEvent {
+    Message(ServerMessage),
+    Disconnected(String),
+}
+
+#[derive(Resource)]
+struct Client {
+    events: UnboundedReceiver<ClientEvent>,
+    outgoing: UnboundedSender<ClientMessage>,
+    connected: bool,
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let (events_sender, events) = unbounded_channel();
+        let (outgoing, outgoing_receiver) = unbounded_channel();
+        let address = self.address.clone();
+
+        spawn_runtime(async move {
+            let reason = match connect(&address, &events_sender, outgoing_receiver).await {
+                Ok(()) => "the server closed the connection".to_string(),
+                Err(err) => err.to_string(),
+            };
+            let _ = events_sender.send(ClientEvent::Disconnected(reason));
+        });
+
+        app.insert_resource(Client {
+            events,
+            outgoing,
+            connected: true,
+        })
+        .add_systems(Startup, |mut simulation: ResMut<Simulation>| {
+            simulation.remote = true;
+        })
+        .add_systems(PostUpdate, client_sync.before(DrawBoard));
+    }
+}
+
+async fn connect(
+    address: &str,
+    events: &UnboundedSender<ClientEvent>,
+    outgoing: UnboundedReceiver<ClientMessage>,
+) -> io::Result<()> {
+    let stream = TcpStream::connect(address).await?;
+    println!("Connected to {}.", address);
+
+    let (mut reader, writer) = stream.into_split();
+    tokio::spawn(write_loop(writer, outgoing));
+
+    let mut codec = codec();
+    let mut buffer = BytesMut::new();
+    while let Some(message) = read_message(&mut reader, &mut codec, &mut buffer).await? {
+        let _ = events.send(ClientEvent::Message(message));
+    }
+    Ok(())
+}
+
+fn client_sync(mut board: ResMut<Board>, mut client: ResMut<Client>) {
+    // Nothing ticks here, so anything dirty is a local edit since the last frame.
+    if client.connected && !board.dirty_tiles.is_empty() {
+        let _ = client.outgoing.send(ClientMessage::Edit {
+            tiles: dirty_changes(&board),
+        });
+    }
+
+    while let Ok(event) = client.events.try_recv() {
+        match event {
+            ClientEvent::Message(ServerMessage::Welcome { tiles }) => {
+                let width = tiles.len();
+                let height = tiles.first().map_or(0, |column| column.len());
+                println!("Joined a {}x{} board.", width, height);
+
+                *board = Board {
+                    tiles: tiles
+                        .into_iter()
+                        .map(|column| column.into_iter().map(Tile::from).collect())
+                        .collect(),
+                    dirty_tiles: Vec::new(),
+                };
+            }
+            ClientEvent::Message(ServerMessage::Delta { tiles }) => {
+                for (x, y, synced) in tiles {
+                    if board
+                        .get(x, y)
+                        .is_some_and(|tile| SyncedTile::from(&tile) != synced)
+                    {
+                        let _ = board.set(x, y, synced.into());
+                    }
+                }
+            }
+            ClientEvent::Disconnected(reason) => {
+                eprintln!("Disconnected: {}", reason);
+                client.connected = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// An app with just a board, like `--headless` without the physics.
+    fn app(board: Board) -> App {
+        let mut app = App::new();
+        app.insert_resource(board)
+            .init_resource::<Simulation>()
+            .add_systems(Last, |mut board: ResMut<Board>| board.clear_dirty_tiles());
+        app
+    }
+
+    /// Updates every app until `done`, failing if that takes more than a few seconds.
+    fn update_until(apps: &mut [&mut App], waiting_for: &str, done: impl Fn(&[&mut App]) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            for app in apps.iter_mut() {
+                app.update();
+            }
+            if done(apps) {
+                return;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for {}",
+                waiting_for
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn size(app: &App) -> (usize, usize) {
+        let board = app.world.resource::<Board>();
+        (board.width(), board.height())
+    }
+
+    fn tile_type(app: &App, x: usize, y: usize) -> TileType {
+        app.world.resource::<Board>().tiles[x][y].tile_type
+    }
+
+    #[test]
+    fn two_clients_share_a_board() {
+        let mut server = app(Board::new(20, 20));
+        server.add_plugins(ServerPlugin {
+            address: "127.0.0.1:0".into(),
+        });
+        update_until(&mut [&mut server], "the server to listen", |apps| {
+            apps[0].world.resource::<Server>().address.is_some()
+        });
+        let address = server.world.resource::<Server>().address.unwrap();
+
+        let mut first = app(Board::new(3, 3));
+        first.add_plugins(ClientPlugin {
+            address: address.to_string(),
+        });
+        let mut second = app(Board::new(3, 3));
+        second.add_plugins(ClientPlugin {
+            address: address.to_string(),
+        });
+        let mut apps = [&mut server, &mut first, &mut second];
+        update_until(&mut apps, "both clients to join", |apps| {
+            size(apps[1]) == (20, 20) && size(apps[2]) == (20, 20)
+        });
+
+        let _ = apps[1]
+            .world
+            .resource_mut::<Board>()
+            .set(4, 5, Tile::from_type(TileType::Sand));
+        update_until(&mut apps, "the edit to reach the other client", |apps| {
+            tile_type(apps[0], 4, 5) == TileType::Sand && tile_type(apps[2], 4, 5) == TileType::Sand
+        });
+
+        // Painted with the largest brush rather than a million tiles across.
+        let _ = apps[1]
+            .world
+            .resource::<Client>()
+            .outgoing
+            .send(ClientMessage::Paint {
+                x: 8,
+                y: 8,
+                radius: 1_000_000,
+                tile_type: TileType::Water,
+            });
+        update_until(&mut apps, "the paint to reach the other client", |apps| {
+            tile_type(apps[2], 8, 8) == TileType::Water
+        });
+        assert_eq!(tile_type(apps[2], 15, 8), TileType::None);
+
+        // Fire burning down keeps its type, only its strength and colour change.
+        let fire = Tile {
+            strength: Some(2),
+            color: Color::rgb_u8(200, 60, 10),
+            ..Tile::from_type(TileType::Fire)
+        };
+        let _ = apps[0].world.resource_mut::<Board>().set(3, 12, fire);
+        update_until(&mut apps, "the fire to reach the clients", |apps| {
+            tile_type(apps[2], 3, 12) == TileType::Fire
+        });
+        let _ = apps[0].world.resource_mut::<Board>().set(
+            3,
+            12,
+            Tile {
+                strength: Some(1),
+                ..fire
+            },
+        );
+        update_until(
+            &mut apps,
+            "the fire's strength to reach the clients",
+            |apps| apps[2].world.resource::<Board>().tiles[3][12].strength == Some(1),
+        );
+        let tile = apps[2].world.resource::<Board>().tiles[3][12];
+        assert_eq!(tile.color.as_rgba_u8(), [200, 60, 10, 255]);
+
+        // Like undo, the same size but most of the board replaced.
+        apps[0]
+            .world
+            .resource_mut::<Board>()
+            .replace_tiles(Board::new(20, 20).tiles);
+        update_until(&mut apps, "the clients to get the replaced board", |apps| {
+            tile_type(apps[1], 4, 5) == TileType::None && tile_type(apps[2], 8, 8) == TileType::None
+        });
+
+        // Like a New board of another size.
+        apps[0]
+            .world
+            .resource_mut::<Board>()
+            .replace_tiles(Board::new(12, 8).tiles);
+        update_until(&mut apps, "the clients to get the new board", |apps| {
+            size(apps[1]) == (12, 8) && size(apps[2]) == (12, 8)
+        });
+    }
+}
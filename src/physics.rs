@@ -1,179 +1,288 @@
+use crate::settings::Settings;
 use crate::{
     board::Board,
+    invariants::{self, Violation},
     tile::{Tile, TileType},
 };
 use bevy::prelude::*;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::{Duration, Instant};
 
 pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, physics);
+        app.init_resource::<Simulation>()
+            .add_systems(Startup, seed_rng)
+            .add_systems(Update, apply_tick_rate)
+            .add_systems(FixedUpdate, physics);
     }
 }
 
-fn physics(mut board: ResMut<Board>) {
+/// The simulation's random numbers, seeded from the settings so runs can be repeated.
+#[derive(Resource)]
+pub struct SimRng(pub StdRng);
+
+fn seed_rng(mut commands: Commands, settings: Res<Settings>) {
+    let rng = match settings.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    commands.insert_resource(SimRng(rng));
+}
+
+fn apply_tick_rate(settings: Res<Settings>, mut time: ResMut<Time<Fixed>>) {
+    if settings.is_changed() {
+        time.set_timestep_hz(settings.tick_rate);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Simulation {
+    pub paused: bool,
+    /// Run a single tick while paused.
+    pub step: bool,
+    /// Check every rule against the invariants, pausing on the first one broken.
+    pub check_invariants: bool,
+    pub violation: Option<Violation>,
+    /// Ticks run since the app started.
+    pub tick: u64,
+    /// How long the last tick took.
+    pub tick_duration: Duration,
+    /// The board is simulated by a server, so nothing ticks here.
+    pub remote: bool,
+}
+
+pub fn physics(
+    mut board: ResMut<Board>,
+    mut simulation: ResMut<Simulation>,
+    mut rng: ResMut<SimRng>,
+) {
+    if simulation.remote || (simulation.paused && !simulation.step) {
+        return;
+    }
+    simulation.step = false;
+
+    let start = Instant::now();
+
+    if !simulation.check_invariants {
+        step(&mut board, &mut rng.0);
+    } else if let Err(violation) = step_checked(&mut board, &mut rng.0) {
+        warn!("{}", violation);
+        simulation.violation = Some(violation);
+        simulation.paused = true;
+        return;
+    }
+
+    simulation.tick += 1;
+    simulation.tick_duration = start.elapsed();
+}
+
+/// The rules a tick applies to each tile, in the order they run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    FireSpread,
+    FireStrength,
+    Flow,
+    Pile,
+    Gravity,
+    Acid,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::FireSpread,
+        Rule::FireStrength,
+        Rule::Flow,
+        Rule::Pile,
+        Rule::Gravity,
+        Rule::Acid,
+    ];
+
+    fn applies_to(&self, tile: &Tile) -> bool {
+        match self {
+            Rule::FireSpread | Rule::FireStrength => tile.tile_type == TileType::Fire,
+            Rule::Flow => tile.flows,
+            Rule::Pile => tile.piles,
+            Rule::Gravity => tile.gravity,
+            Rule::Acid => tile.tile_type == TileType::Acid,
+        }
+    }
+
+    /// Applies the rule, or says why it couldn't, such as a write off the board.
+    fn apply(
+        &self,
+        board: &mut Board,
+        x: usize,
+        y: usize,
+        rng: &mut StdRng,
+    ) -> Result<Board, String> {
+        match self {
+            Rule::FireSpread => fire_spread(board, x, y, rng),
+            Rule::FireStrength => fire_strength(board, x, y),
+            Rule::Flow => flow(board, x, y, rng),
+            Rule::Pile => pile(board, x, y, rng),
+            Rule::Gravity => gravity(board, x, y),
+            Rule::Acid => acid(board, x, y),
+        }
+    }
+
+    /// Rules that only move tiles around, so every type's count stays the same.
+    pub fn conserves_tiles(&self) -> bool {
+        matches!(self, Rule::Flow | Rule::Pile | Rule::Gravity)
+    }
+}
+
+/// Advances the board by one tick.
+pub fn step(board: &mut Board, rng: &mut StdRng) {
+    // Can't fail without checking.
+    let _ = run_step(board, rng, false);
+}
+
+/// Advances the board by one tick, checking each rule as it's applied. On the first
+/// broken invariant the board is left as it was before the tick.
+pub fn step_checked(board: &mut Board, rng: &mut StdRng) -> Result<(), Violation> {
+    run_step(board, rng, true)
+}
+
+fn run_step(board: &mut Board, rng: &mut StdRng, checked: bool) -> Result<(), Violation> {
     let mut new_board = board.clone();
 
     for (x, row) in board.tiles.iter().enumerate() {
         for (y, tile) in row.iter().enumerate() {
-            if tile.tile_type == TileType::Fire {
-                new_board = fire_spread(&mut new_board, x, y);
-                new_board = fire_strength(&mut new_board, x, y);
-            }
-            if tile.flows {
-                new_board = flow(&mut new_board, x, y);
-            }
-            if tile.piles {
-                new_board = pile(&mut new_board, x, y);
-            }
-            if tile.gravity {
-                new_board = gravity(&mut new_board, x, y);
-            }
-            if tile.tile_type == TileType::Acid {
-                new_board = acid(&mut new_board, x, y);
+            for rule in Rule::ALL.iter().filter(|rule| rule.applies_to(tile)) {
+                let before = checked.then(|| new_board.clone());
+                new_board = rule
+                    .apply(&mut new_board, x, y, rng)
+                    .map_err(|message| Violation {
+                        rule: *rule,
+                        x,
+                        y,
+                        message,
+                    })?;
+                if let Some(before) = before {
+                    invariants::check(*rule, x, y, &before, &new_board)?;
+                }
             }
         }
     }
 
     board.tiles = new_board.tiles;
     board.dirty_tiles = new_board.dirty_tiles;
+    Ok(())
 }
 
-fn gravity(board: &mut Board, x: usize, y: usize) -> Board {
+fn gravity(board: &mut Board, x: usize, y: usize) -> Result<Board, String> {
     if board.tiles.first().unwrap().len() <= y {
-        return board.clone();
+        return Ok(board.clone());
     }
 
     let center = board.tiles[x][y];
     let bottom = board.tiles[x][y.saturating_sub(1)];
 
     if bottom.gas || (center.solid && !bottom.solid) {
-        board.swap(x, y, x, y.saturating_sub(1))
+        board.swap(x, y, x, y.saturating_sub(1))?;
     }
 
-    board.clone()
+    Ok(board.clone())
 }
 
-fn pile(board: &mut Board, x: usize, y: usize) -> Board {
-    if board.height() <= y {
-        return board.clone();
-    }
-
-    let bottom = board.tiles[x][y.saturating_sub(1)];
-    let bottom_right = board.tiles[x.saturating_add(1)][y.saturating_sub(1)];
-    let bottom_left = board.tiles[x.saturating_sub(1)][y.saturating_sub(1)];
+fn pile(board: &mut Board, x: usize, y: usize, rng: &mut StdRng) -> Result<Board, String> {
+    let bottom = match offset(board, x, y, 0, -1) {
+        Some((x, y)) => board.tiles[x][y],
+        None => return Ok(board.clone()),
+    };
 
     if bottom.gas {
         // do nothing
-        return board.clone();
-    } else if !bottom_left.solid && !bottom_right.solid {
-        if rand::random::<bool>() {
-            board.swap(x, y, x.saturating_sub(1), y.saturating_sub(1));
-        } else {
-            board.swap(x, y, x.saturating_add(1), y.saturating_sub(1));
-        }
-    } else if bottom_left.gas {
-        board.swap(x, y, x.saturating_sub(1), y.saturating_sub(1));
-    } else if bottom_right.gas {
-        board.swap(x, y, x.saturating_add(1), y.saturating_sub(1));
+        return Ok(board.clone());
+    }
+
+    // Off the board counts as solid.
+    let open = |(x, y): &(usize, usize)| !board.tiles[*x][*y].solid;
+    let bottom_left = offset(board, x, y, -1, -1).filter(open);
+    let bottom_right = offset(board, x, y, 1, -1).filter(open);
+    let is_gas = |(x, y): (usize, usize)| board.tiles[x][y].gas;
+
+    let target = match (bottom_left, bottom_right) {
+        (Some(left), Some(right)) => Some(if rng.gen::<bool>() { left } else { right }),
+        (Some(left), None) if is_gas(left) => Some(left),
+        (None, Some(right)) if is_gas(right) => Some(right),
+        _ => None,
+    };
+
+    if let Some((target_x, target_y)) = target {
+        board.swap(x, y, target_x, target_y)?;
     }
 
-    board.clone()
+    Ok(board.clone())
 }
 
-fn flow(board: &mut Board, x: usize, y: usize) -> Board {
-    if board.tiles.first().unwrap().len() <= y {
-        return board.clone();
+fn flow(board: &mut Board, x: usize, y: usize, rng: &mut StdRng) -> Result<Board, String> {
+    // The bottom edge of the board holds liquid up like a floor.
+    if offset(board, x, y, 0, -1).is_some_and(|(x, y)| board.tiles[x][y].gas) {
+        return Ok(board.clone());
     }
 
-    let mut bottom = match board.get(x, y.saturating_sub(1)) {
-        Some(tile) => tile,
-        None => return board.clone(),
-    };
-    let mut right = match board.get(x.saturating_add(1), y) {
-        Some(tile) => tile,
-        None => return board.clone(),
-    };
-    let mut left = match board.get(x.saturating_sub(1), y) {
-        Some(tile) => tile,
-        None => return board.clone(),
+    let is_gas = |(x, y): &(usize, usize)| board.tiles[*x][*y].gas;
+    let left = offset(board, x, y, -1, 0).filter(is_gas);
+    let right = offset(board, x, y, 1, 0).filter(is_gas);
+
+    let target = match (left, right) {
+        (Some(left), Some(right)) => Some(if rng.gen::<bool>() { left } else { right }),
+        (left, right) => right.or(left),
     };
 
-    if !bottom.gas {
-        if right.gas && left.gas {
-            if rand::random::<bool>() {
-                board.swap(x, y, x.saturating_sub(1), y);
-            } else {
-                board.swap(x, y, x.saturating_add(1), y);
-            }
-        } else if right.gas {
-            board.swap(x, y, x.saturating_add(1), y);
-        } else if left.gas {
-            board.swap(x, y, x.saturating_sub(1), y);
-        }
+    if let Some((target_x, target_y)) = target {
+        board.swap(x, y, target_x, target_y)?;
     }
 
-    board.clone()
+    Ok(board.clone())
 }
 
-fn fire_spread(board: &mut Board, x: usize, y: usize) -> Board {
-    let mut center = match board.get(x, y) {
+fn fire_spread(board: &mut Board, x: usize, y: usize, rng: &mut StdRng) -> Result<Board, String> {
+    let center = match board.get(x, y) {
         Some(tile) => tile,
-        None => return board.clone(),
-    };
-
-    let mut top = match board.get(x, y.saturating_add(1)) {
-        Some(tile) => tile,
-        None => return board.clone(),
-    };
-    let mut bottom = match board.get(x, y.saturating_sub(1)) {
-        Some(tile) => tile,
-        None => return board.clone(),
-    };
-    let mut right = match board.get(x.saturating_add(1), y) {
-        Some(tile) => tile,
-        None => return board.clone(),
-    };
-    let mut left = match board.get(x.saturating_sub(1), y) {
-        Some(tile) => tile,
-        None => return board.clone(),
+        None => return Ok(board.clone()),
     };
 
     let mut should_spread_fire = false;
 
-    for tile in [&mut top, &mut bottom, &mut right, &mut left] {
-        if tile.gas && one_in(3) {
-            *tile = Tile::from_type(TileType::Fire);
+    for (dx, dy) in NEIGHBOURS {
+        let (nx, ny) = match offset(board, x, y, dx, dy) {
+            Some(position) => position,
+            None => continue,
+        };
+        let mut tile = board.tiles[nx][ny];
+
+        if tile.gas && one_in(rng, 3) {
+            tile = Tile::from_type(TileType::Fire);
             if let Some(strength) = center.strength {
-                (tile).strength = Some(strength.saturating_sub(1));
+                tile.strength = Some(strength.saturating_sub(1));
             }
             should_spread_fire = true;
-        } else if tile.flammable && one_in(5) {
-            *tile = Tile::from_type(TileType::Fire);
+        } else if tile.flammable && one_in(rng, 5) {
+            tile = Tile::from_type(TileType::Fire);
             should_spread_fire = true;
         }
         if tile.strength == Some(0) {
-            *tile = Tile::from_type(TileType::None);
+            tile = Tile::from_type(TileType::None);
         }
+
+        board.set(nx, ny, tile)?;
     }
 
     if should_spread_fire {
-        center = Tile::from_type(TileType::None);
+        board.set(x, y, Tile::from_type(TileType::None))?;
     }
 
-    board.set(x, y, center);
-    board.set(x, y.saturating_add(1), top);
-    board.set(x, y.saturating_sub(1), bottom);
-    board.set(x.saturating_add(1), y, right);
-    board.set(x.saturating_sub(1), y, left);
-
-    board.clone()
+    Ok(board.clone())
 }
 
-fn fire_strength(board: &mut Board, x: usize, y: usize) -> Board {
-    let mut tile = board.get(x, y).unwrap();
+fn fire_strength(board: &mut Board, x: usize, y: usize) -> Result<Board, String> {
+    let mut tile = board
+        .get(x, y)
+        .ok_or(format!("Index ({}, {}) out of bounds.", x, y))?;
 
     if let Some(ref mut strength) = tile.strength {
         if *strength > 0 {
@@ -182,43 +291,51 @@ fn fire_strength(board: &mut Board, x: usize, y: usize) -> Board {
             tile = Tile::from_type(TileType::None);
         }
     }
-    dbg!(tile);
 
-    board.set(x, y, tile).unwrap();
+    board.set(x, y, tile)?;
 
-    board.clone()
+    Ok(board.clone())
 }
 
-fn acid(board: &mut Board, x: usize, y: usize) -> Board {
-    let mut top = board.tiles[x][y.saturating_add(1)];
-    let mut bottom = board.tiles[x][y.saturating_sub(1)];
-    let mut right = board.tiles[x.saturating_add(1)][y];
-    let mut left = board.tiles[x.saturating_sub(1)][y];
+fn acid(board: &mut Board, x: usize, y: usize) -> Result<Board, String> {
+    // It may have fallen or flowed away earlier in the tick.
+    if board.tiles[x][y].tile_type != TileType::Acid {
+        return Ok(board.clone());
+    }
 
     let mut should_die = false;
 
-    for tile in [&mut top, &mut bottom, &mut right, &mut left] {
-        if tile.tile_type != TileType::Acid
-            && tile.tile_type != TileType::None
-            && tile.tile_type != TileType::Wall
+    for (dx, dy) in NEIGHBOURS {
+        let (nx, ny) = match offset(board, x, y, dx, dy) {
+            Some(position) => position,
+            None => continue,
+        };
+        let tile_type = board.tiles[nx][ny].tile_type;
+
+        if tile_type != TileType::Acid && tile_type != TileType::None && tile_type != TileType::Wall
         {
-            *tile = Tile::from_type(TileType::None);
+            board.set(nx, ny, Tile::from_type(TileType::None))?;
             should_die = true;
         }
     }
 
     if should_die {
-        board.set(x, y, Tile::from_type(TileType::None)).unwrap();
+        board.set(x, y, Tile::from_type(TileType::None))?;
     }
 
-    board.set(x, y.saturating_add(1), top);
-    board.set(x, y.saturating_sub(1), bottom);
-    board.set(x.saturating_add(1), y, right);
-    board.set(x.saturating_sub(1), y, left);
+    Ok(board.clone())
+}
+
+/// Top, bottom, right and left, in the order the rules look at them.
+const NEIGHBOURS: [(isize, isize); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
 
-    board.clone()
+/// The position `(dx, dy)` away, or `None` if that's off the board.
+fn offset(board: &Board, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+    let x = x.checked_add_signed(dx)?;
+    let y = y.checked_add_signed(dy)?;
+    (x < board.width() && y < board.height()).then_some((x, y))
 }
 
-fn one_in(every: usize) -> bool {
-    rand::thread_rng().gen_range(1..every) == 1
+fn one_in(rng: &mut StdRng, every: usize) -> bool {
+    rng.gen_range(1..every) == 1
 }
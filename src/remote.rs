@@ -0,0 +1,394 @@
+use crate::{
+    board::Board,
+    controls::UndoHistory,
+    multiplayer::spawn_runtime,
+    physics::{self, SimRng, Simulation},
+    stats::BoardStats,
+    tile::{Tile, TileType},
+    timeline::Timeline,
+};
+use bevy::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+#[cfg(unix)]
+use std::{os::unix::fs::FileTypeExt, path::Path};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+/// A command sent to `--control`, one JSON object per line. Each gets one line back,
+/// `{"ok": true, ...}` with any results or `{"ok": false, "error": "..."}`.
+///
+/// ```text
+/// {"command": "set", "x": 4, "y": 10, "tile_type": "Sand"}
+/// {"command": "fill_rect", "x": 1, "y": 1, "width": 5, "height": 3, "tile_type": "Water"}
+/// {"command": "get_region", "x": 0, "y": 0, "width": 5, "height": 3}
+/// {"command": "step", "ticks": 10}
+/// {"command": "pause", "paused": false}
+/// {"command": "load", "path": "board.txt"}
+/// {"command": "save", "path": "board.json"}
+/// {"command": "stats"}
+/// ```
+///
+/// `get_region` answers with `rows`, in the text board format with the top row first.
+/// `step` answers once every tick has run, a few each frame so the app keeps drawing.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Set {
+        x: usize,
+        y: usize,
+        tile_type: TileType,
+    },
+    FillRect {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        tile_type: TileType,
+    },
+    GetRegion {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    /// Runs ticks as fast as possible, paused or not.
+    Step {
+        #[serde(default = "one")]
+        ticks: u64,
+    },
+    Pause {
+        #[serde(default = "yes")]
+        paused: bool,
+    },
+    Load {
+        path: PathBuf,
+    },
+    Save {
+        path: PathBuf,
+    },
+    Stats,
+}
+
+fn one() -> u64 {
+    1
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// A request waiting to be handled between ticks.
+#[derive(Event)]
+struct RemoteRequest {
+    request: Request,
+    reply: UnboundedSender<Value>,
+}
+
+/// Listens for JSON-lines commands on a localhost TCP address or a Unix socket path.
+pub struct RemotePlugin {
+    pub address: String,
+}
+
+#[derive(Resource)]
+struct RemoteQueue(UnboundedReceiver<RemoteRequest>);
+
+/// `step` requests still running: ticks left, and where to send the answer.
+#[derive(Resource, Default)]
+struct PendingSteps(Vec<(u64, UnboundedSender<Value>)>);
+
+/// How long `step` requests may spend ticking each frame.
+const STEP_BUDGET: Duration = Duration::from_millis(10);
+
+/// Whether `--control` names a TCP address rather than a socket path.
+fn is_tcp_address(address: &str) -> bool {
+    address.parse::<SocketAddr>().is_ok() || address.starts_with("localhost:")
+}
+
+impl Plugin for RemotePlugin {
+    fn build(&self, app: &mut App) {
+        let (requests, queue) = unbounded_channel();
+        let address = self.address.clone();
+
+        spawn_runtime(async move {
+            if let Err(err) = listen(&address, requests).await {
+                eprintln!("error: couldn't listen on {}: {}", address, err);
+                std::process::exit(1);
+            }
+        });
+
+        app.add_event::<RemoteRequest>()
+            .insert_resource(RemoteQueue(queue))
+            .init_resource::<PendingSteps>()
+            .add_systems(Update, (queue_requests, handle_requests, run_steps).chain());
+    }
+}
+
+async fn listen(address: &str, requests: UnboundedSender<RemoteRequest>) -> io::Result<()> {
+    if is_tcp_address(address) {
+        let listener = TcpListener::bind(address).await?;
+        println!("Listening for commands on {}.", address);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(serve(stream, requests.clone()));
+        }
+    } else {
+        listen_on_socket(address, requests).await
+    }
+}
+
+#[cfg(unix)]
+async fn listen_on_socket(
+    address: &str,
+    requests: UnboundedSender<RemoteRequest>,
+) -> io::Result<()> {
+    remove_stale_socket(Path::new(address))?;
+    let listener = UnixListener::bind(address)?;
+    println!("Listening for commands on {}.", address);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(serve(stream, requests.clone()));
+    }
+}
+
+#[cfg(not(unix))]
+async fn listen_on_socket(
+    address: &str,
+    _requests: UnboundedSender<RemoteRequest>,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} isn't a TCP address, and socket paths only work on Unix; use one like 127.0.0.1:7878",
+            address
+        ),
+    ))
+}
+
+/// Removes a socket left behind by a run that didn't exit cleanly. Anything else at
+/// `path` is left alone, so a mistyped address can't delete a file.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and isn't a socket", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Answers one connection's requests in order until it closes.
+async fn serve(
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+    requests: UnboundedSender<RemoteRequest>,
+) -> io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let (reply, mut replies) = unbounded_channel();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let queued = requests.send(RemoteRequest {
+                    request,
+                    reply: reply.clone(),
+                });
+                match queued {
+                    Ok(()) => replies
+                        .recv()
+                        .await
+                        .unwrap_or_else(|| error("shutting down")),
+                    Err(_) => error("shutting down"),
+                }
+            }
+            Err(err) => error(&err.to_string()),
+        };
+
+        writer
+            .write_all(format!("{}\n", response).as_bytes())
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn error(message: &str) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+fn ok(results: Value) -> Value {
+    let mut response = Map::new();
+    response.insert("ok".into(), true.into());
+    if let Value::Object(results) = results {
+        response.extend(results);
+    }
+    Value::Object(response)
+}
+
+fn queue_requests(mut queue: ResMut<RemoteQueue>, mut events: EventWriter<RemoteRequest>) {
+    while let Ok(request) = queue.0.try_recv() {
+        events.send(request);
+    }
+}
+
+/// Checks a rectangle fits on the board.
+fn check_region(
+    board: &Board,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> Result<(), String> {
+    let fits = |start: usize, length: usize, size: usize| {
+        start.checked_add(length).is_some_and(|end| end <= size)
+    };
+    if !fits(x, width, board.width()) || !fits(y, height, board.height()) {
+        return Err(format!(
+            "{}x{} at ({}, {}) doesn't fit on the {}x{} board",
+            width,
+            height,
+            x,
+            y,
+            board.width(),
+            board.height()
+        ));
+    }
+    Ok(())
+}
+
+fn handle_requests(
+    mut events: EventReader<RemoteRequest>,
+    mut board: ResMut<Board>,
+    mut simulation: ResMut<Simulation>,
+    mut steps: ResMut<PendingSteps>,
+    mut history: Option<ResMut<UndoHistory>>,
+    mut timeline: Option<ResMut<Timeline>>,
+) {
+    for event in events.read() {
+        let result = match &event.request {
+            Request::Set { x, y, tile_type } => board
+                .set(*x, *y, Tile::from_type(*tile_type))
+                .map(|()| json!({})),
+            Request::FillRect {
+                x,
+                y,
+                width,
+                height,
+                tile_type,
+            } => check_region(&board, *x, *y, *width, *height).map(|()| {
+                for tile_x in *x..x + width {
+                    for tile_y in *y..y + height {
+                        let _ = board.set(tile_x, tile_y, Tile::from_type(*tile_type));
+                    }
+                }
+                json!({})
+            }),
+            Request::GetRegion {
+                x,
+                y,
+                width,
+                height,
+            } => check_region(&board, *x, *y, *width, *height).map(|()| {
+                let region = Board {
+                    tiles: board.tiles[*x..x + width]
+                        .iter()
+                        .map(|column| column[*y..y + height].to_vec())
+                        .collect(),
+                    dirty_tiles: Vec::new(),
+                };
+                let rows: Vec<String> = if *width == 0 {
+                    Vec::new()
+                } else {
+                    region.to_ascii().lines().map(String::from).collect()
+                };
+                json!({ "rows": rows })
+            }),
+            Request::Step { ticks } => {
+                steps.0.push((*ticks, event.reply.clone()));
+                continue;
+            }
+            Request::Pause { paused } => {
+                simulation.paused = *paused;
+                Ok(json!({ "paused": simulation.paused }))
+            }
+            Request::Load { path } => Board::load_from_file(path).map(|loaded| {
+                board.replace_tiles(loaded.tiles);
+                // Neither is registered without a window.
+                if let Some(history) = history.as_mut() {
+                    history.clear();
+                }
+                if let Some(timeline) = timeline.as_mut() {
+                    timeline.clear();
+                }
+                json!({ "width": board.width(), "height": board.height() })
+            }),
+            Request::Save { path } => board
+                .save_to_file(path)
+                .map(|()| json!({}))
+                .map_err(|err| format!("Couldn't write {}: {}", path.display(), err)),
+            Request::Stats => {
+                let stats = BoardStats::new(&board, None);
+                let population: Map<String, Value> = stats
+                    .population
+                    .iter()
+                    .map(|(tile_type, count)| (format!("{:?}", tile_type), (*count).into()))
+                    .collect();
+                Ok(json!({
+                    "tick": simulation.tick,
+                    "paused": simulation.paused,
+                    "width": board.width(),
+                    "height": board.height(),
+                    "population": population,
+                }))
+            }
+        };
+
+        let response = match result {
+            Ok(results) => ok(results),
+            Err(err) => error(&err),
+        };
+        let _ = event.reply.send(response);
+    }
+}
+
+/// Runs the ticks `step` requests asked for, oldest first, until this frame's budget is
+/// spent, answering each request once its last tick has run.
+fn run_steps(
+    mut steps: ResMut<PendingSteps>,
+    mut board: ResMut<Board>,
+    mut simulation: ResMut<Simulation>,
+    mut rng: ResMut<SimRng>,
+) {
+    let start = Instant::now();
+    while let Some((remaining, reply)) = steps.0.first_mut() {
+        if *remaining == 0 {
+            let _ = reply.send(ok(json!({ "tick": simulation.tick })));
+            steps.0.remove(0);
+            continue;
+        }
+        if start.elapsed() >= STEP_BUDGET {
+            return;
+        }
+        physics::step(&mut board, &mut rng.0);
+        simulation.tick += 1;
+        *remaining -= 1;
+    }
+}
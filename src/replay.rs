@@ -0,0 +1,386 @@
+use crate::{
+    board::Board,
+    cli::ReplayArgs,
+    physics::{self, physics, SimRng, Simulation},
+    tile::{Tile, TileType},
+};
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+pub const REPLAY_VERSION: u32 = 2;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Recorder>()
+            .add_systems(FixedUpdate, track_ticks.after(physics))
+            .add_systems(Last, record_edits);
+    }
+}
+
+/// What a replay keeps of each tile. Colours are picked again on playback and never
+/// change how a tile behaves, but a half-burnt tile does.
+pub type TileState = (TileType, Option<u8>);
+
+/// A tile changed by an edit: where it is and what it became.
+pub type TileEdit = (usize, usize, TileState);
+
+fn tile_from_state((tile_type, strength): TileState) -> Tile {
+    Tile {
+        strength,
+        ..Tile::from_type(tile_type)
+    }
+}
+
+fn tile_states(board: &Board) -> Vec<Vec<TileState>> {
+    board
+        .tiles
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|tile| (tile.tile_type, tile.strength))
+                .collect()
+        })
+        .collect()
+}
+
+fn board_from_states(states: &[Vec<TileState>]) -> Board {
+    Board {
+        tiles: states
+            .iter()
+            .map(|column| column.iter().copied().map(tile_from_state).collect())
+            .collect(),
+        dirty_tiles: Vec::new(),
+    }
+}
+
+/// Something the player did to the board, between two ticks.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "input", rename_all = "snake_case")]
+pub enum Input {
+    /// A dab of the brush, applied with `Board::set_radius`.
+    Brush {
+        x: usize,
+        y: usize,
+        tile_type: TileType,
+        radius: isize,
+    },
+    /// Any other edit, like a paste or an undo.
+    Tiles { tiles: Vec<TileEdit> },
+    /// The whole board was replaced, e.g. by a new board of a different size.
+    Board { tiles: Vec<Vec<TileState>> },
+}
+
+impl Input {
+    pub fn apply(&self, board: &mut Board) {
+        match self {
+            Input::Brush {
+                x,
+                y,
+                tile_type,
+                radius,
+            } => {
+                let _ = board.set_radius(*x, *y, Tile::from_type(*tile_type), *radius);
+            }
+            Input::Tiles { tiles } => {
+                for (x, y, state) in tiles {
+                    let _ = board.set(*x, *y, tile_from_state(*state));
+                }
+            }
+            Input::Board { tiles } => *board = board_from_states(tiles),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimedInput {
+    /// Ticks run since the recording started when this happened.
+    pub tick: u64,
+    #[serde(flatten)]
+    pub input: Input,
+}
+
+/// Everything needed to play a session back exactly: the starting board, the seed and
+/// every input with the tick it happened on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub board: Vec<Vec<TileState>>,
+    pub inputs: Vec<TimedInput>,
+    /// Ticks run before the recording stopped.
+    pub ticks: u64,
+    /// What the board looked like when it stopped, to check playback against.
+    pub final_board: Vec<Vec<TileState>>,
+}
+
+impl Replay {
+    pub fn save_to_file(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+        let replay: Replay = serde_json::from_str(&contents)
+            .map_err(|err| format!("{} isn't a replay: {}", path.display(), err))?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "{} is a version {} replay, this build plays version {}",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+        Ok(replay)
+    }
+
+    /// Runs the recording from the start and returns the board it ends on.
+    pub fn play(&self) -> Board {
+        let mut board = board_from_states(&self.board);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut inputs = self.inputs.iter().peekable();
+
+        for tick in 0..=self.ticks {
+            while let Some(timed) = inputs.next_if(|timed| timed.tick == tick) {
+                timed.input.apply(&mut board);
+            }
+            if tick < self.ticks {
+                physics::step(&mut board, &mut rng);
+            }
+            board.clear_dirty_tiles();
+        }
+
+        board
+    }
+}
+
+struct Recording {
+    replay: Replay,
+    start_tick: u64,
+    /// The board as the recording knows it. Anything else that differs is an edit.
+    expected: Board,
+}
+
+/// Records inputs while a recording is running.
+#[derive(Resource, Default)]
+pub struct Recorder {
+    recording: Option<Recording>,
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Starts recording from the current board, reseeding the simulation so the
+    /// recording knows where its random numbers start.
+    pub fn start(&mut self, board: &Board, simulation: &Simulation, rng: &mut SimRng, seed: u64) {
+        rng.0 = StdRng::seed_from_u64(seed);
+        self.recording = Some(Recording {
+            replay: Replay {
+                version: REPLAY_VERSION,
+                seed,
+                board: tile_states(board),
+                inputs: Vec::new(),
+                ticks: 0,
+                final_board: Vec::new(),
+            },
+            start_tick: simulation.tick,
+            expected: board.clone(),
+        });
+    }
+
+    pub fn stop(&mut self, board: &Board, simulation: &Simulation) -> Option<Replay> {
+        let recording = self.recording.take()?;
+        let mut replay = recording.replay;
+        replay.ticks = simulation.tick - recording.start_tick;
+        replay.final_board = tile_states(board);
+        Some(replay)
+    }
+
+    /// Records a brush dab that was just applied to the board.
+    pub fn brush(
+        &mut self,
+        simulation: &Simulation,
+        x: usize,
+        y: usize,
+        tile_type: TileType,
+        radius: isize,
+    ) {
+        if let Some(recording) = self.recording.as_mut() {
+            let input = Input::Brush {
+                x,
+                y,
+                tile_type,
+                radius,
+            };
+            input.apply(&mut recording.expected);
+            recording.push(simulation, input);
+        }
+    }
+}
+
+impl Recording {
+    fn push(&mut self, simulation: &Simulation, input: Input) {
+        self.replay.inputs.push(TimedInput {
+            tick: simulation.tick - self.start_tick,
+            input,
+        });
+    }
+}
+
+fn track_ticks(board: Res<Board>, mut recorder: ResMut<Recorder>) {
+    if let Some(recording) = recorder.recording.as_mut() {
+        recording.expected = board.clone();
+    }
+}
+
+/// Turns every change since the last tick that wasn't a recorded brush into an input.
+fn record_edits(board: Res<Board>, simulation: Res<Simulation>, mut recorder: ResMut<Recorder>) {
+    let Some(recording) = recorder.recording.as_mut() else {
+        return;
+    };
+
+    let expected = &recording.expected;
+    let input = if expected.width() != board.width() || expected.height() != board.height() {
+        Input::Board {
+            tiles: tile_states(&board),
+        }
+    } else {
+        let tiles: Vec<TileEdit> = board
+            .tiles
+            .iter()
+            .zip(expected.tiles.iter())
+            .enumerate()
+            .flat_map(|(x, (column, expected_column))| {
+                column
+                    .iter()
+                    .zip(expected_column.iter())
+                    .enumerate()
+                    .filter(|(_, (tile, expected))| {
+                        (tile.tile_type, tile.strength) != (expected.tile_type, expected.strength)
+                    })
+                    .map(move |(y, (tile, _))| (x, y, (tile.tile_type, tile.strength)))
+            })
+            .collect();
+        if tiles.is_empty() {
+            return;
+        }
+        Input::Tiles { tiles }
+    };
+
+    recording.push(&simulation, input);
+    recording.expected = board.clone();
+}
+
+/// Runs `particles replay`.
+pub fn run(args: &ReplayArgs) -> Result<(), String> {
+    let replay = Replay::load_from_file(&args.replay)?;
+    let board = replay.play();
+
+    if let Some(out) = &args.out {
+        board
+            .save_to_file(out)
+            .map_err(|err| format!("Couldn't write {}: {}", out.display(), err))?;
+    }
+
+    if tile_states(&board) != replay.final_board {
+        return Err(format!(
+            "played {} ticks and {} inputs, but the final board differs from the recording",
+            replay.ticks,
+            replay.inputs.len()
+        ));
+    }
+
+    println!(
+        "Played {} ticks and {} inputs, the final board matches the recording.",
+        replay.ticks,
+        replay.inputs.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fire(strength: u8) -> Tile {
+        Tile {
+            strength: Some(strength),
+            ..Tile::from_type(TileType::Fire)
+        }
+    }
+
+    #[test]
+    fn strengths_are_recorded_and_played_back() {
+        let mut board = Board::new(8, 8);
+        board.set(3, 3, fire(2)).unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(board)
+            .init_resource::<Simulation>()
+            .init_resource::<Recorder>()
+            .add_systems(Update, record_edits);
+        app.world
+            .resource_scope(|world, mut recorder: Mut<Recorder>| {
+                let mut rng = SimRng(StdRng::seed_from_u64(0));
+                recorder.start(
+                    world.resource::<Board>(),
+                    world.resource::<Simulation>(),
+                    &mut rng,
+                    7,
+                );
+            });
+
+        // Only the strength changes, which is still an edit.
+        app.world.resource_mut::<Board>().tiles[3][3].strength = Some(1);
+        app.update();
+        let mut resized = Board::new(6, 6);
+        resized.set(2, 2, fire(3)).unwrap();
+        app.insert_resource(resized);
+        app.update();
+
+        let replay = app
+            .world
+            .resource_scope(|world, mut recorder: Mut<Recorder>| {
+                recorder
+                    .stop(world.resource::<Board>(), world.resource::<Simulation>())
+                    .unwrap()
+            });
+        assert_eq!(replay.board[3][3], (TileType::Fire, Some(2)));
+        assert!(matches!(
+            &replay.inputs[0].input,
+            Input::Tiles { tiles } if tiles == &[(3, 3, (TileType::Fire, Some(1)))]
+        ));
+        assert!(matches!(replay.inputs[1].input, Input::Board { .. }));
+        assert_eq!(replay.final_board[2][2], (TileType::Fire, Some(3)));
+
+        let json = serde_json::to_string(&replay).unwrap();
+        let loaded: Replay = serde_json::from_str(&json).unwrap();
+        assert_eq!(tile_states(&loaded.play()), replay.final_board);
+    }
+
+    #[test]
+    fn playback_with_a_different_strength_differs() {
+        let mut board = Board::new(8, 8);
+        board.set(3, 3, fire(2)).unwrap();
+        let mut replay = Replay {
+            version: REPLAY_VERSION,
+            seed: 7,
+            board: tile_states(&board),
+            inputs: Vec::new(),
+            ticks: 0,
+            final_board: tile_states(&board),
+        };
+        assert_eq!(tile_states(&replay.play()), replay.final_board);
+
+        replay.final_board[3][3].1 = Some(1);
+        assert_ne!(tile_states(&replay.play()), replay.final_board);
+    }
+}
@@ -0,0 +1,188 @@
+//! Golden boards in `scenarios/`. Each `<name>.txt` is run for a fixed number of ticks
+//! and compared with `<name>.expected.txt`. Run with `BLESS=1` to overwrite the expected
+//! boards with what the physics does now.
+
+use crate::{board::Board, consts::SCENARIOS_PATH, physics};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{env, fs, path::PathBuf};
+
+/// A small board in `scenarios/` that's run for a fixed number of ticks and compared
+/// against the board it's expected to end up as.
+struct Scenario {
+    name: &'static str,
+    ticks: u64,
+    seed: u64,
+}
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "gravity",
+        ticks: 8,
+        seed: 0,
+    },
+    Scenario {
+        name: "gravity_floor",
+        ticks: 4,
+        seed: 0,
+    },
+    Scenario {
+        name: "pile",
+        ticks: 20,
+        seed: 0,
+    },
+    Scenario {
+        name: "pile_edges",
+        ticks: 10,
+        seed: 0,
+    },
+    Scenario {
+        name: "flow",
+        ticks: 20,
+        seed: 0,
+    },
+    Scenario {
+        name: "flow_edges",
+        ticks: 10,
+        seed: 0,
+    },
+    Scenario {
+        name: "fire_decay",
+        ticks: 10,
+        seed: 0,
+    },
+    Scenario {
+        name: "fire_spread",
+        ticks: 6,
+        seed: 0,
+    },
+    Scenario {
+        name: "fire_corners",
+        ticks: 3,
+        seed: 0,
+    },
+    Scenario {
+        name: "acid",
+        ticks: 10,
+        seed: 0,
+    },
+    Scenario {
+        name: "acid_edges",
+        ticks: 3,
+        seed: 0,
+    },
+];
+
+impl Scenario {
+    fn start_path(&self) -> PathBuf {
+        scenarios_dir().join(format!("{}.txt", self.name))
+    }
+
+    fn expected_path(&self) -> PathBuf {
+        scenarios_dir().join(format!("{}.expected.txt", self.name))
+    }
+
+    /// Loads the starting board and runs it, checking invariants along the way.
+    fn run(&self) -> Result<Board, String> {
+        let mut board = Board::load_from_file(&self.start_path())?;
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        for tick in 1..=self.ticks {
+            physics::step_checked(&mut board, &mut rng)
+                .map_err(|violation| format!("{} on tick {}: {}", self.name, tick, violation))?;
+            board.clear_dirty_tiles();
+        }
+
+        Ok(board)
+    }
+}
+
+fn scenarios_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SCENARIOS_PATH)
+}
+
+/// Prints the expected and actual boards next to each other, marking rows that differ.
+fn print_diff(expected: &str, actual: &str) {
+    let width = expected.lines().map(str::len).max().unwrap_or(0).max(8);
+    println!("    {:<width$}   actual", "expected", width = width);
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => break,
+            (expected, actual) => {
+                let (expected, actual) = (expected.unwrap_or(""), actual.unwrap_or(""));
+                let marker = if expected == actual { ' ' } else { '!' };
+                println!(
+                    "  {} {:<width$}   {}",
+                    marker,
+                    expected,
+                    actual,
+                    width = width
+                );
+            }
+        }
+    }
+}
+
+/// Every starting board in `scenarios/`, by name.
+fn scenario_files() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(scenarios_dir())
+        .unwrap()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let name = name.strip_suffix(".txt")?;
+            (!name.ends_with(".expected")).then(|| name.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn golden_scenarios() {
+    let bless = env::var_os("BLESS").is_some();
+    let mut failed = Vec::new();
+
+    let files = scenario_files();
+    for name in files.iter() {
+        let scenario = SCENARIOS
+            .iter()
+            .find(|scenario| scenario.name == name.as_str())
+            .unwrap_or_else(|| panic!("scenarios/{}.txt isn't listed in SCENARIOS", name));
+        let actual = scenario.run().unwrap().to_ascii();
+
+        if bless {
+            fs::write(scenario.expected_path(), &actual).unwrap();
+            println!("blessed {}", scenario.name);
+            continue;
+        }
+
+        let expected = Board::load_from_file(&scenario.expected_path())
+            .unwrap()
+            .to_ascii();
+        if expected != actual {
+            println!(
+                "FAILED  {} after {} ticks with seed {}",
+                scenario.name, scenario.ticks, scenario.seed
+            );
+            print_diff(&expected, &actual);
+            failed.push(scenario.name);
+        }
+    }
+
+    for scenario in SCENARIOS.iter() {
+        assert!(
+            files.iter().any(|name| name == scenario.name),
+            "scenarios/{}.txt is missing",
+            scenario.name
+        );
+    }
+    assert!(
+        failed.is_empty(),
+        "{} of {} scenarios failed: {}, rerun with BLESS=1 if that's intended",
+        failed.len(),
+        files.len(),
+        failed.join(", ")
+    );
+}
@@ -0,0 +1,772 @@
+use crate::{cli::Cli, consts::SETTINGS_PATH, tile::TileType};
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::Write,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Bump this and add a step to `migrate` whenever the layout of the file changes.
+pub const SETTINGS_VERSION: u64 = 1;
+
+pub const RADIUS_RANGE: RangeInclusive<isize> = 0..=4;
+pub const BOARD_SIZE_RANGE: RangeInclusive<usize> = 3..=1000;
+pub const TICK_RATE_RANGE: RangeInclusive<f64> = 1.0..=240.0;
+pub const SNAPSHOT_INTERVAL_RANGE: RangeInclusive<u64> = 1..=600;
+pub const TIMELINE_MEMORY_RANGE: RangeInclusive<usize> = 1..=1024;
+pub const AUTOSAVE_INTERVAL_RANGE: RangeInclusive<u64> = 10..=3600;
+pub const AUTOSAVE_KEEP_RANGE: RangeInclusive<usize> = 1..=20;
+
+/// How long the settings have to stay unchanged before they're written.
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+pub struct SettingsPlugin {
+    pub cli: Cli,
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let path = self
+            .cli
+            .config
+            .clone()
+            .unwrap_or_else(|| config_dir().join(SETTINGS_PATH));
+
+        // Loaded straight away so every other plugin's startup systems can read it.
+        let (loaded, errors) = if self.cli.config.is_some() || path.exists() {
+            Settings::load_from_file(&path)
+        } else {
+            // Older builds kept the settings in the working directory.
+            Settings::load_from_file(Path::new(SETTINGS_PATH))
+        };
+        for error in errors.iter() {
+            println!("Settings: {}", error);
+        }
+
+        let mut settings = loaded.clone();
+        self.cli.apply(&mut settings);
+
+        app.insert_resource(SettingsSaver::new(&loaded, self.cli.clone()))
+            .insert_resource(settings)
+            .insert_resource(SettingsErrors(errors))
+            .insert_resource(SettingsPath(path))
+            .add_systems(Update, save_settings);
+    }
+}
+
+/// Where the settings file lives. Other per-user files, like the bindings, go next to it.
+#[derive(Resource, Clone, Debug)]
+pub struct SettingsPath(pub PathBuf);
+
+impl SettingsPath {
+    /// A file in the same directory as the settings file.
+    pub fn sibling(&self, file_name: &str) -> PathBuf {
+        self.0.with_file_name(file_name)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/particles`, falling back to `~/.config/particles`, then the working directory.
+pub fn config_dir() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    match base {
+        Some(base) => base.join("particles"),
+        None => PathBuf::from("."),
+    }
+}
+
+/// Writes to a temporary file next to `path` and renames it into place, so a crash
+/// part way through leaves either the old file or the new one, never half of one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let mut file = File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+#[derive(Resource, Clone, Debug, Serialize, PartialEq)]
+pub struct Settings {
+    pub version: u64,
+    pub brush: BrushSettings,
+    pub board: BoardSettings,
+    /// Physics ticks per second.
+    pub tick_rate: f64,
+    /// Seed for the simulation's random numbers. `None` picks a new one every run.
+    pub seed: Option<u64>,
+    pub ui: UiLayout,
+    pub render_mode: RenderMode,
+    pub timeline: TimelineSettings,
+    pub autosave: AutosaveSettings,
+    /// Fields this version doesn't know about, kept so they survive a save.
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct BrushSettings {
+    pub tile_type: TileType,
+    pub radius: isize,
+    pub paint_mode: PaintMode,
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct BoardSettings {
+    pub width: usize,
+    pub height: usize,
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
+}
+
+/// How the rewind timeline keeps its snapshots.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TimelineSettings {
+    /// Ticks between snapshots.
+    pub interval: u64,
+    /// Megabytes the snapshots may use before the oldest are dropped.
+    pub memory_mb: usize,
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
+}
+
+/// How often the board is saved in the background in case the app crashes.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct AutosaveSettings {
+    pub enabled: bool,
+    /// Seconds between autosaves.
+    pub interval: u64,
+    /// Autosave files to rotate through.
+    pub keep: usize,
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
+}
+
+/// Which windows are open.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct UiLayout {
+    /// Hides every window at once.
+    pub visible: bool,
+    pub clipboard: bool,
+    pub stamps: bool,
+    pub inspector: bool,
+    pub bindings: bool,
+    pub stats: bool,
+    pub timeline: bool,
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
+}
+
+/// How painted or pasted tiles treat what's already on the board.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaintMode {
+    /// Only fill tiles that are currently empty.
+    #[default]
+    Fill,
+    /// Replace whatever is there.
+    Overwrite,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderMode {
+    /// Each tile's own colour.
+    #[default]
+    Normal,
+    /// One flat colour per tile type.
+    Flat,
+    /// Highlights the tiles that changed this tick.
+    Dirty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            brush: BrushSettings::default(),
+            board: BoardSettings::default(),
+            tick_rate: 60.,
+            seed: None,
+            ui: UiLayout::default(),
+            render_mode: RenderMode::default(),
+            timeline: TimelineSettings::default(),
+            autosave: AutosaveSettings::default(),
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl Default for TimelineSettings {
+    fn default() -> Self {
+        TimelineSettings {
+            interval: 30,
+            memory_mb: 64,
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        AutosaveSettings {
+            enabled: true,
+            interval: 60,
+            keep: 3,
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        BrushSettings {
+            tile_type: TileType::Sand,
+            radius: 0,
+            paint_mode: PaintMode::default(),
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl Default for BoardSettings {
+    fn default() -> Self {
+        BoardSettings {
+            width: 100,
+            height: 100,
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl Default for UiLayout {
+    fn default() -> Self {
+        UiLayout {
+            visible: true,
+            clipboard: true,
+            stamps: true,
+            inspector: true,
+            bindings: false,
+            stats: false,
+            timeline: false,
+            unknown: Map::new(),
+        }
+    }
+}
+
+/// A problem with one field of the settings file. The field falls back to its default.
+#[derive(Clone, Debug)]
+pub struct SettingsError {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+/// Problems found while loading the settings, shown in the settings window.
+#[derive(Resource, Default)]
+pub struct SettingsErrors(pub Vec<SettingsError>);
+
+/// Upgrades an older settings object one version at a time.
+fn migrate(map: &mut Map<String, Value>) -> Result<(), String> {
+    let mut version = match map.get("version") {
+        None => 0,
+        Some(value) => value
+            .as_u64()
+            .ok_or(format!("version {} isn't a number", value))?,
+    };
+
+    if version > SETTINGS_VERSION {
+        return Err(format!(
+            "file is version {}, this build only knows up to {}",
+            version, SETTINGS_VERSION
+        ));
+    }
+
+    while version < SETTINGS_VERSION {
+        match version {
+            // Version 0 kept the brush at the top level.
+            0 => {
+                let mut brush = Map::new();
+                for key in ["tile_type", "radius", "paint_mode"] {
+                    if let Some(value) = map.remove(key) {
+                        brush.insert(key.to_string(), value);
+                    }
+                }
+                map.insert("brush".to_string(), Value::Object(brush));
+            }
+            _ => unreachable!(),
+        }
+        version += 1;
+    }
+
+    map.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+    Ok(())
+}
+
+/// Reads fields one at a time so that one bad value only resets that value.
+struct Reader {
+    errors: Vec<SettingsError>,
+}
+
+impl Reader {
+    fn error(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(SettingsError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn field<T: DeserializeOwned>(
+        &mut self,
+        map: &mut Map<String, Value>,
+        prefix: &str,
+        key: &str,
+        target: &mut T,
+    ) {
+        let Some(value) = map.remove(key) else {
+            return;
+        };
+
+        match serde_json::from_value(value) {
+            Ok(value) => *target = value,
+            Err(err) => self.error(&format!("{}{}", prefix, key), err.to_string()),
+        }
+    }
+
+    fn object(&mut self, map: &mut Map<String, Value>, key: &str) -> Map<String, Value> {
+        match map.remove(key) {
+            None => Map::new(),
+            Some(Value::Object(object)) => object,
+            Some(value) => {
+                self.error(key, format!("expected an object, found {}", value));
+                Map::new()
+            }
+        }
+    }
+
+    fn range<T: PartialOrd + fmt::Display + Copy>(
+        &mut self,
+        field: &str,
+        value: &mut T,
+        range: RangeInclusive<T>,
+        default: T,
+    ) {
+        if !range.contains(value) {
+            self.error(
+                field,
+                format!(
+                    "{} is outside {}..={}, using {}",
+                    value,
+                    range.start(),
+                    range.end(),
+                    default
+                ),
+            );
+            *value = default;
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_json(contents: &str) -> (Self, Vec<SettingsError>) {
+        let mut settings = Settings::default();
+        let mut reader = Reader { errors: Vec::new() };
+
+        let mut map = match serde_json::from_str::<Value>(contents) {
+            Ok(Value::Object(map)) => map,
+            Ok(_) => {
+                reader.error("", "settings file isn't a JSON object");
+                return (settings, reader.errors);
+            }
+            Err(err) => {
+                reader.error("", format!("settings file isn't valid JSON: {}", err));
+                return (settings, reader.errors);
+            }
+        };
+
+        if let Err(err) = migrate(&mut map) {
+            reader.error("version", err);
+        }
+        map.remove("version");
+
+        let mut brush = reader.object(&mut map, "brush");
+        reader.field(
+            &mut brush,
+            "brush.",
+            "tile_type",
+            &mut settings.brush.tile_type,
+        );
+        reader.field(&mut brush, "brush.", "radius", &mut settings.brush.radius);
+        reader.field(
+            &mut brush,
+            "brush.",
+            "paint_mode",
+            &mut settings.brush.paint_mode,
+        );
+        settings.brush.unknown = brush;
+
+        let mut board = reader.object(&mut map, "board");
+        reader.field(&mut board, "board.", "width", &mut settings.board.width);
+        reader.field(&mut board, "board.", "height", &mut settings.board.height);
+        settings.board.unknown = board;
+
+        reader.field(&mut map, "", "tick_rate", &mut settings.tick_rate);
+        reader.field(&mut map, "", "seed", &mut settings.seed);
+
+        let mut ui = reader.object(&mut map, "ui");
+        reader.field(&mut ui, "ui.", "visible", &mut settings.ui.visible);
+        reader.field(&mut ui, "ui.", "clipboard", &mut settings.ui.clipboard);
+        reader.field(&mut ui, "ui.", "stamps", &mut settings.ui.stamps);
+        reader.field(&mut ui, "ui.", "inspector", &mut settings.ui.inspector);
+        reader.field(&mut ui, "ui.", "bindings", &mut settings.ui.bindings);
+        reader.field(&mut ui, "ui.", "stats", &mut settings.ui.stats);
+        reader.field(&mut ui, "ui.", "timeline", &mut settings.ui.timeline);
+        settings.ui.unknown = ui;
+
+        reader.field(&mut map, "", "render_mode", &mut settings.render_mode);
+
+        let mut timeline = reader.object(&mut map, "timeline");
+        reader.field(
+            &mut timeline,
+            "timeline.",
+            "interval",
+            &mut settings.timeline.interval,
+        );
+        reader.field(
+            &mut timeline,
+            "timeline.",
+            "memory_mb",
+            &mut settings.timeline.memory_mb,
+        );
+        settings.timeline.unknown = timeline;
+
+        let mut autosave = reader.object(&mut map, "autosave");
+        reader.field(
+            &mut autosave,
+            "autosave.",
+            "enabled",
+            &mut settings.autosave.enabled,
+        );
+        reader.field(
+            &mut autosave,
+            "autosave.",
+            "interval",
+            &mut settings.autosave.interval,
+        );
+        reader.field(
+            &mut autosave,
+            "autosave.",
+            "keep",
+            &mut settings.autosave.keep,
+        );
+        settings.autosave.unknown = autosave;
+        settings.unknown = map;
+
+        let defaults = Settings::default();
+        reader.range(
+            "brush.radius",
+            &mut settings.brush.radius,
+            RADIUS_RANGE,
+            defaults.brush.radius,
+        );
+        reader.range(
+            "board.width",
+            &mut settings.board.width,
+            BOARD_SIZE_RANGE,
+            defaults.board.width,
+        );
+        reader.range(
+            "board.height",
+            &mut settings.board.height,
+            BOARD_SIZE_RANGE,
+            defaults.board.height,
+        );
+        reader.range(
+            "tick_rate",
+            &mut settings.tick_rate,
+            TICK_RATE_RANGE,
+            defaults.tick_rate,
+        );
+        reader.range(
+            "timeline.interval",
+            &mut settings.timeline.interval,
+            SNAPSHOT_INTERVAL_RANGE,
+            defaults.timeline.interval,
+        );
+        reader.range(
+            "timeline.memory_mb",
+            &mut settings.timeline.memory_mb,
+            TIMELINE_MEMORY_RANGE,
+            defaults.timeline.memory_mb,
+        );
+        reader.range(
+            "autosave.interval",
+            &mut settings.autosave.interval,
+            AUTOSAVE_INTERVAL_RANGE,
+            defaults.autosave.interval,
+        );
+        reader.range(
+            "autosave.keep",
+            &mut settings.autosave.keep,
+            AUTOSAVE_KEEP_RANGE,
+            defaults.autosave.keep,
+        );
+
+        (settings, reader.errors)
+    }
+
+    /// Loads the settings, falling back to the defaults for anything missing or invalid.
+    /// A corrupt file is moved aside so the next save doesn't destroy it.
+    pub fn load_from_file(path: &Path) -> (Self, Vec<SettingsError>) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return (Settings::default(), Vec::new()),
+        };
+
+        let (settings, mut errors) = Settings::from_json(&contents);
+
+        if errors.iter().any(|error| error.field.is_empty()) {
+            let mut corrupt = path.as_os_str().to_owned();
+            corrupt.push(".corrupt");
+            let corrupt = PathBuf::from(corrupt);
+            if fs::rename(path, &corrupt).is_ok() {
+                errors.push(SettingsError {
+                    field: String::new(),
+                    message: format!("the old file was moved to {}", corrupt.display()),
+                });
+            }
+        }
+
+        (settings, errors)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// Tracks what was last written so the file is only touched when something changed.
+#[derive(Resource)]
+struct SettingsSaver {
+    /// Command line overrides, which are only for this run and never saved.
+    cli: Cli,
+    /// The settings before the command line overrode them.
+    loaded: Settings,
+    saved: Settings,
+    /// The settings as of the last change.
+    pending: Settings,
+    /// When the settings last changed, if they haven't been saved since.
+    changed_at: Option<Duration>,
+}
+
+impl SettingsSaver {
+    fn new(loaded: &Settings, cli: Cli) -> Self {
+        SettingsSaver {
+            cli,
+            loaded: loaded.clone(),
+            saved: loaded.clone(),
+            pending: loaded.clone(),
+            changed_at: None,
+        }
+    }
+
+    /// Notes any change to `settings`, and returns them without the command line
+    /// overrides once they've stayed the same for `SAVE_DELAY`, if they differ from
+    /// what was last saved.
+    fn poll(&mut self, settings: &Settings, now: Duration) -> Option<Settings> {
+        let mut settings = settings.clone();
+        self.cli.unapply(&mut settings, &self.loaded);
+        if settings != self.pending {
+            self.pending = settings.clone();
+            self.changed_at = Some(now);
+        }
+
+        let changed_at = self.changed_at?;
+        if now - changed_at < SAVE_DELAY {
+            return None;
+        }
+        self.changed_at = None;
+        (self.pending != self.saved).then(|| self.pending.clone())
+    }
+}
+
+fn save_settings(
+    settings: Res<Settings>,
+    path: Res<SettingsPath>,
+    time: Res<Time<Real>>,
+    mut saver: ResMut<SettingsSaver>,
+) {
+    let now = time.elapsed();
+    // Windows borrow the settings mutably every frame, so `poll` compares the contents.
+    if !settings.is_changed() && saver.changed_at.is_none() {
+        return;
+    }
+    let Some(changed) = saver.poll(&settings, now) else {
+        return;
+    };
+
+    match changed.to_json() {
+        Ok(json) => match write_atomic(&path.0, json.as_bytes()) {
+            Ok(()) => saver.saved = changed,
+            Err(err) => {
+                println!("Failed to save settings :(");
+                println!("{:#?}", err);
+                saver.changed_at = Some(now);
+            }
+        },
+        Err(err) => println!("Failed to serialize settings: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp directory for one test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("particles-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file() {
+        let dir = temp_dir("write-atomic");
+        let path = dir.join("nested").join(SETTINGS_PATH);
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        let files: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(files.len(), 1, "the temporary file was left behind");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saved_settings_load_the_same() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join(SETTINGS_PATH);
+        let mut settings = Settings::default();
+        settings.board.width = 321;
+        settings.brush.tile_type = TileType::Water;
+        settings
+            .unknown
+            .insert("from_the_future".into(), Value::from(1));
+
+        write_atomic(&path, settings.to_json().unwrap().as_bytes()).unwrap();
+        let (loaded, errors) = Settings::load_from_file(&path);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(loaded, settings);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_is_moved_aside() {
+        let dir = temp_dir("corrupt");
+        let path = dir.join(SETTINGS_PATH);
+        fs::write(&path, "{ not json").unwrap();
+
+        let (settings, errors) = Settings::load_from_file(&path);
+
+        assert_eq!(settings, Settings::default());
+        assert!(errors.iter().any(|error| error.field.is_empty()));
+        assert!(!path.exists());
+        let corrupt = dir.join(format!("{}.corrupt", SETTINGS_PATH));
+        assert_eq!(fs::read_to_string(corrupt).unwrap(), "{ not json");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_field_only_resets_that_field() {
+        let (settings, errors) =
+            Settings::from_json(r#"{"version": 1, "tick_rate": 30, "board": {"width": 0}}"#);
+
+        assert_eq!(settings.tick_rate, 30.);
+        assert_eq!(settings.board.width, Settings::default().board.width);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "board.width");
+    }
+
+    #[test]
+    fn saves_once_changes_stop() {
+        let second = Duration::from_secs(1);
+        let mut settings = Settings::default();
+        let mut saver = SettingsSaver::new(&settings, Cli::default());
+        assert_eq!(saver.poll(&settings, second * 5), None);
+
+        // Dragging a slider changes the settings every frame.
+        for frame in 0..100 {
+            settings.tick_rate = 60. + frame as f64;
+            let now = Duration::from_millis(frame * 16);
+            assert_eq!(saver.poll(&settings, now), None);
+        }
+
+        let last_change = Duration::from_millis(99 * 16);
+        assert_eq!(saver.poll(&settings, last_change + second / 2), None);
+        assert_eq!(
+            saver.poll(&settings, last_change + second),
+            Some(settings.clone())
+        );
+        saver.saved = settings.clone();
+        assert_eq!(saver.poll(&settings, last_change + second * 3), None);
+    }
+
+    #[test]
+    fn changing_back_saves_nothing() {
+        let mut settings = Settings::default();
+        let mut saver = SettingsSaver::new(&settings, Cli::default());
+
+        settings.seed = Some(4);
+        assert_eq!(saver.poll(&settings, Duration::ZERO), None);
+        settings.seed = None;
+        assert_eq!(saver.poll(&settings, Duration::from_millis(100)), None);
+        assert_eq!(saver.poll(&settings, Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn command_line_overrides_are_not_saved() {
+        let loaded = Settings::default();
+        let cli = Cli::parse(["--width", "500", "--seed", "9"].map(String::from)).unwrap();
+        let mut settings = loaded.clone();
+        cli.apply(&mut settings);
+        let mut saver = SettingsSaver::new(&loaded, cli);
+        assert_eq!(saver.poll(&settings, Duration::from_secs(5)), None);
+
+        settings.tick_rate = 30.;
+        assert_eq!(saver.poll(&settings, Duration::from_secs(6)), None);
+        let saved = saver.poll(&settings, Duration::from_secs(8)).unwrap();
+        assert_eq!(saved.tick_rate, 30.);
+        assert_eq!(saved.board.width, loaded.board.width);
+        assert_eq!(saved.seed, loaded.seed);
+    }
+}
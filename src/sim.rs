@@ -0,0 +1,121 @@
+use crate::{
+    board::Board,
+    cli::SimArgs,
+    export::{self, FrameWriter, GifRecorder, Region},
+    physics,
+    settings::Settings,
+    stats::BoardStats,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    time::Instant,
+};
+
+/// Runs `particles sim`: no window, no Bevy app, just the physics as fast as it goes.
+pub fn run(args: &SimArgs) -> Result<(), String> {
+    let mut board = Board::load_from_file(&args.board)?;
+    let mut rng = StdRng::seed_from_u64(args.seed);
+
+    let mut stats = match &args.stats {
+        Some(path) => {
+            let file = File::create(path)
+                .map_err(|err| format!("Couldn't create {}: {}", path.display(), err))?;
+            let mut writer = BufWriter::new(file);
+            writeln!(writer, "{}", BoardStats::csv_header()).map_err(|err| err.to_string())?;
+            writeln!(writer, "{}", BoardStats::new(&board, None).csv_row(0))
+                .map_err(|err| err.to_string())?;
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let mut frames = match &args.frames {
+        Some(dir) => {
+            let mut frames = FrameWriter::new(dir, args.frame_every, args.scale)?;
+            frames.tick(&board, 0)?;
+            Some(frames)
+        }
+        None => None,
+    };
+
+    let mut gif = match &args.gif {
+        Some(path) => {
+            let region = args.region.unwrap_or_else(|| Region::whole(&board));
+            if !region.fits(&board) {
+                return Err(format!(
+                    "--region {}x{} at ({}, {}) doesn't fit on the {}x{} board",
+                    region.width,
+                    region.height,
+                    region.x,
+                    region.y,
+                    board.width(),
+                    board.height()
+                ));
+            }
+            // Played back at the default tick rate.
+            let delay = export::gif_delay(args.frame_every, Settings::default().tick_rate);
+            let mut gif = GifRecorder::new(path, region, args.frame_every, args.scale, delay)?;
+            gif.tick(&board, 0)?;
+            Some(gif)
+        }
+        None => None,
+    };
+
+    let start = Instant::now();
+    let mut changed = 0;
+
+    for tick in 1..=args.ticks {
+        let previous = board.clone();
+        if args.check {
+            physics::step_checked(&mut board, &mut rng)
+                .map_err(|violation| format!("tick {}: {}", tick, violation))?;
+        } else {
+            physics::step(&mut board, &mut rng);
+        }
+        // Nothing renders here to clear them, so they'd pile up forever.
+        board.clear_dirty_tiles();
+
+        let tick_stats = BoardStats::new(&board, Some(&previous));
+        changed += tick_stats.changed;
+
+        if let Some(writer) = stats.as_mut() {
+            writeln!(writer, "{}", tick_stats.csv_row(tick)).map_err(|err| err.to_string())?;
+        }
+        if let Some(frames) = frames.as_mut() {
+            frames.tick(&board, tick)?;
+        }
+        if let Some(gif) = gif.as_mut() {
+            gif.tick(&board, tick)?;
+        }
+    }
+
+    if let Some(mut writer) = stats {
+        writer.flush().map_err(|err| err.to_string())?;
+    }
+
+    let elapsed = start.elapsed();
+    board
+        .save_to_file(&args.out)
+        .map_err(|err| format!("Couldn't write {}: {}", args.out.display(), err))?;
+    if let Some(png) = &args.png {
+        export::save_png(&board, png, args.scale)?;
+    }
+
+    println!(
+        "Ran {} ticks in {:.2?} ({:.2?} per tick), {} tile changes.",
+        args.ticks,
+        elapsed,
+        elapsed.div_f64(args.ticks.max(1) as f64),
+        changed
+    );
+    if let (Some(dir), Some(frames)) = (&args.frames, frames) {
+        println!("Saved {} frames to {}.", frames.frames(), dir.display());
+    }
+    if let (Some(path), Some(gif)) = (&args.gif, gif) {
+        println!("Saved {} frames to {}.", gif.finish()?, path.display());
+    }
+
+    Ok(())
+}
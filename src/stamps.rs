@@ -0,0 +1,289 @@
+use crate::{
+    ascii::Legend,
+    clipboard::{Clipboard, Pattern, Tool},
+    consts::STAMPS_PATH,
+    controls::ui_visible,
+    settings::Settings,
+    tile::{Tile, TileType},
+};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+pub struct StampsPlugin;
+
+impl Plugin for StampsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_stamps)
+            .add_systems(Update, stamps_window.run_if(ui_visible));
+    }
+}
+
+const THUMBNAIL_SIZE: f32 = 48.;
+
+/// A named pattern that can be placed on the board like a paste.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stamp {
+    pub name: String,
+    pub pattern: Pattern,
+}
+
+impl Stamp {
+    pub fn save_to_file(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+
+        let file_name: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = dir.join(format!("{}.json", file_name));
+
+        let serialized = serde_json::to_string_pretty(self)?;
+        File::create(&path)?.write_all(serialized.as_bytes())?;
+        Ok(path)
+    }
+
+    pub fn load_from_file(path: &Path) -> std::io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let stamp: Stamp = serde_json::from_str(&contents)?;
+        stamp
+            .pattern
+            .validate()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        Ok(stamp)
+    }
+}
+
+struct LibraryEntry {
+    stamp: Stamp,
+    builtin: bool,
+    /// Thumbnail colours, worked out once so the random tile colours don't flicker.
+    thumbnail: Vec<Vec<egui::Color32>>,
+}
+
+impl LibraryEntry {
+    fn new(stamp: Stamp, builtin: bool) -> Self {
+        let thumbnail = stamp
+            .pattern
+            .tiles
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|tile_type| {
+                        let [r, g, b, a] = Tile::from_type(*tile_type).color.as_rgba_u8();
+                        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        LibraryEntry {
+            stamp,
+            builtin,
+            thumbnail,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct StampLibrary {
+    entries: Vec<LibraryEntry>,
+    new_name: String,
+}
+
+impl StampLibrary {
+    fn is_builtin(&self, name: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.builtin && entry.stamp.name == name)
+    }
+
+    /// Adds a user stamp, replacing any other user stamp with the same name. Built-in
+    /// stamps can't be replaced.
+    pub fn add(&mut self, stamp: Stamp) -> Result<(), String> {
+        if self.is_builtin(&stamp.name) {
+            return Err(format!("'{}' is the name of a built-in stamp", stamp.name));
+        }
+        self.entries.retain(|entry| entry.stamp.name != stamp.name);
+        self.entries.push(LibraryEntry::new(stamp, false));
+        Ok(())
+    }
+}
+
+/// Builds a pattern from rows of text, top row first, using the board file legend.
+/// Unknown characters are empty.
+fn pattern_from_rows(rows: &[&str]) -> Pattern {
+    let legend = Legend::default();
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let height = rows.len();
+    let mut tiles = vec![vec![TileType::None; height]; width];
+
+    for (row_index, row) in rows.iter().enumerate() {
+        let y = height - 1 - row_index;
+        for (x, c) in row.chars().enumerate() {
+            tiles[x][y] = legend.tile_type(c).unwrap_or(TileType::None);
+        }
+    }
+
+    Pattern {
+        width,
+        height,
+        tiles,
+    }
+}
+
+fn builtin_stamps() -> Vec<Stamp> {
+    vec![
+        Stamp {
+            name: "Hourglass".into(),
+            pattern: pattern_from_rows(&[
+                "#########",
+                "#sssssss#",
+                " #sssss# ",
+                "  #sss#  ",
+                "   # #   ",
+                "  #   #  ",
+                " #     # ",
+                "#       #",
+                "#########",
+            ]),
+        },
+        Stamp {
+            name: "U-tube".into(),
+            pattern: pattern_from_rows(&[
+                "#   #   #",
+                "#   #   #",
+                "#~~~#   #",
+                "#~~~#   #",
+                "#~~~~~~~#",
+                "#########",
+            ]),
+        },
+        Stamp {
+            name: "Furnace".into(),
+            pattern: pattern_from_rows(&[
+                "#       #",
+                "#  www  #",
+                "# wwwww #",
+                "#wwwwwww#",
+                "#fffffff#",
+                "#########",
+            ]),
+        },
+    ]
+}
+
+fn load_stamps(mut commands: Commands) {
+    let mut library = StampLibrary::default();
+
+    for stamp in builtin_stamps() {
+        library.entries.push(LibraryEntry::new(stamp, true));
+    }
+
+    if let Ok(dir) = fs::read_dir(STAMPS_PATH) {
+        let mut paths: Vec<PathBuf> = dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let result = Stamp::load_from_file(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|stamp| library.add(stamp));
+            if let Err(err) = result {
+                println!("Failed to load stamp {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    commands.insert_resource(library);
+}
+
+fn thumbnail(ui: &mut egui::Ui, colors: &[Vec<egui::Color32>]) {
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0., egui::Color32::BLACK);
+
+    let width = colors.len();
+    let height = colors.first().map_or(0, |column| column.len());
+    let cell = THUMBNAIL_SIZE / width.max(height).max(1) as f32;
+
+    for (x, column) in colors.iter().enumerate() {
+        for (y, color) in column.iter().enumerate() {
+            let min = rect.left_top() + egui::vec2(x as f32 * cell, (height - 1 - y) as f32 * cell);
+            painter.rect_filled(
+                egui::Rect::from_min_size(min, egui::vec2(cell, cell)),
+                0.,
+                *color,
+            );
+        }
+    }
+}
+
+fn stamps_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut library: ResMut<StampLibrary>,
+    mut clipboard: ResMut<Clipboard>,
+    mut tool: ResMut<Tool>,
+) {
+    egui::Window::new("Stamps")
+        .open(&mut settings.ui.stamps)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.)
+                .show(ui, |ui| {
+                    for entry in library.entries.iter() {
+                        ui.horizontal(|ui| {
+                            thumbnail(ui, &entry.thumbnail);
+                            ui.vertical(|ui| {
+                                ui.label(&entry.stamp.name);
+                                if ui.button("Place").clicked() {
+                                    clipboard.pattern = Some(entry.stamp.pattern.clone());
+                                    *tool = Tool::Paste;
+                                }
+                            });
+                        });
+                    }
+                });
+
+            ui.separator();
+            ui.label("Save clipboard as stamp");
+            ui.text_edit_singleline(&mut library.new_name);
+            let name = library.new_name.trim().to_string();
+            let builtin = library.is_builtin(&name);
+            if builtin {
+                ui.colored_label(egui::Color32::LIGHT_RED, "A built-in stamp has that name");
+            }
+            let can_save = clipboard.pattern.is_some() && !name.is_empty() && !builtin;
+            if ui
+                .add_enabled(can_save, egui::Button::new("Save"))
+                .clicked()
+            {
+                if let Some(pattern) = &clipboard.pattern {
+                    let stamp = Stamp {
+                        name,
+                        pattern: pattern.clone(),
+                    };
+                    match stamp.save_to_file(Path::new(STAMPS_PATH)) {
+                        Ok(_) => {
+                            let _ = library.add(stamp);
+                            library.new_name.clear();
+                        }
+                        Err(err) => println!("Failed to save stamp: {}", err),
+                    }
+                }
+            }
+        });
+}
@@ -0,0 +1,329 @@
+use crate::{
+    board::Board,
+    consts::STATS_PATH,
+    controls::ui_visible,
+    physics::{physics, Simulation},
+    settings::Settings,
+    tile::{TileType, MATERIALS},
+};
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts};
+use std::{collections::VecDeque, fmt::Write, fs, time::Duration};
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatsHistory>()
+            .add_systems(FixedUpdate, record_stats.after(physics))
+            .add_systems(Update, stats_window.run_if(ui_visible));
+    }
+}
+
+/// Ticks of history the graphs scroll through.
+const HISTORY_LENGTH: usize = 600;
+const GRAPH_HEIGHT: f32 = 80.;
+
+/// Counts for one tick of the simulation.
+#[derive(Clone, Debug, Default)]
+pub struct BoardStats {
+    /// Number of tiles of each type, in `MATERIALS` order.
+    pub population: Vec<(TileType, usize)>,
+    /// Tiles whose type differs from the previous tick.
+    pub changed: usize,
+}
+
+impl BoardStats {
+    /// Counts the board, comparing against `previous` if there is one.
+    pub fn new(board: &Board, previous: Option<&Board>) -> Self {
+        let mut population: Vec<(TileType, usize)> =
+            MATERIALS.iter().map(|mat| (mat.tile_type, 0)).collect();
+
+        for column in board.tiles.iter() {
+            for tile in column.iter() {
+                if let Some((_, count)) = population
+                    .iter_mut()
+                    .find(|(tile_type, _)| *tile_type == tile.tile_type)
+                {
+                    *count += 1;
+                }
+            }
+        }
+
+        BoardStats {
+            population,
+            changed: previous.map_or(0, |previous| changed_tiles(previous, board)),
+        }
+    }
+
+    pub fn count(&self, tile_type: TileType) -> usize {
+        self.population
+            .iter()
+            .find(|(t, _)| *t == tile_type)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    pub fn csv_header() -> String {
+        let mut header = String::from("tick,changed");
+        for mat in MATERIALS.iter() {
+            header.push_str(&format!(",{:?}", mat.tile_type));
+        }
+        header
+    }
+
+    pub fn csv_row(&self, tick: u64) -> String {
+        let mut row = format!("{},{}", tick, self.changed);
+        for (_, count) in self.population.iter() {
+            row.push_str(&format!(",{}", count));
+        }
+        row
+    }
+}
+
+/// Number of tiles whose type differs between two boards of the same size.
+pub fn changed_tiles(before: &Board, after: &Board) -> usize {
+    before
+        .tiles
+        .iter()
+        .zip(after.tiles.iter())
+        .map(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .filter(|(a, b)| a.tile_type != b.tile_type)
+                .count()
+        })
+        .sum()
+}
+
+struct Sample {
+    tick: u64,
+    stats: BoardStats,
+    tick_duration: Duration,
+}
+
+/// Stats for the last `HISTORY_LENGTH` ticks.
+#[derive(Resource, Default)]
+pub struct StatsHistory {
+    samples: VecDeque<Sample>,
+    /// The board as of the last sample, to count changes against.
+    previous: Option<Board>,
+    /// Result of the last CSV export.
+    export_message: Option<String>,
+}
+
+impl StatsHistory {
+    /// The history in the same CSV format as `particles sim --stats`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = BoardStats::csv_header();
+        csv.push('\n');
+        for sample in self.samples.iter() {
+            let _ = writeln!(csv, "{}", sample.stats.csv_row(sample.tick));
+        }
+        csv
+    }
+}
+
+fn record_stats(board: Res<Board>, simulation: Res<Simulation>, mut history: ResMut<StatsHistory>) {
+    if history
+        .samples
+        .back()
+        .is_some_and(|sample| sample.tick == simulation.tick)
+    {
+        return;
+    }
+
+    let stats = BoardStats::new(&board, history.previous.as_ref());
+    history.samples.push_back(Sample {
+        tick: simulation.tick,
+        stats,
+        tick_duration: simulation.tick_duration,
+    });
+    if history.samples.len() > HISTORY_LENGTH {
+        history.samples.pop_front();
+    }
+    history.previous = Some(board.clone());
+}
+
+fn color32(color: Color) -> egui::Color32 {
+    let [r, g, b, _] = color.as_rgba_u8();
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// Draws each series as a line, newest on the right, scaled to the largest value shown.
+fn graph(ui: &mut egui::Ui, series: &[(egui::Color32, Vec<f32>)]) {
+    let size = egui::vec2(ui.available_width().max(200.), GRAPH_HEIGHT);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2., egui::Color32::from_black_alpha(120));
+
+    let max = series
+        .iter()
+        .flat_map(|(_, values)| values.iter())
+        .fold(1f32, |max, value| max.max(*value));
+    let step = rect.width() / (HISTORY_LENGTH - 1) as f32;
+
+    for (color, values) in series {
+        let points: Vec<egui::Pos2> = values
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(age, value)| {
+                egui::pos2(
+                    rect.right() - age as f32 * step,
+                    rect.bottom() - rect.height() * value / max,
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, *color)));
+    }
+}
+
+fn stats_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut history: ResMut<StatsHistory>,
+    diagnostics: Res<DiagnosticsStore>,
+) {
+    let fps = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed());
+
+    egui::Window::new("Stats")
+        .open(&mut settings.ui.stats)
+        .show(contexts.ctx_mut(), |ui| {
+            let Some(latest) = history.samples.back() else {
+                ui.label("Waiting for the first tick.");
+                return;
+            };
+
+            egui::Grid::new("stats").striped(true).show(ui, |ui| {
+                ui.label("FPS");
+                ui.label(fps.map_or("-".into(), |fps| format!("{:.0}", fps)));
+                ui.end_row();
+
+                ui.label("Tick");
+                ui.label(format!("{} ({:.2?})", latest.tick, latest.tick_duration));
+                ui.end_row();
+
+                ui.label("Changed");
+                ui.label(latest.stats.changed.to_string());
+                ui.end_row();
+
+                for (tile_type, count) in latest.stats.population.iter() {
+                    if *tile_type == TileType::None {
+                        continue;
+                    }
+                    ui.colored_label(color32(tile_type.base_color()), format!("{:?}", tile_type));
+                    ui.label(count.to_string());
+                    ui.end_row();
+                }
+            });
+
+            ui.label("Population");
+            let population: Vec<(egui::Color32, Vec<f32>)> = MATERIALS
+                .iter()
+                .filter(|mat| mat.tile_type != TileType::None)
+                .map(|mat| {
+                    let values = history
+                        .samples
+                        .iter()
+                        .map(|sample| sample.stats.count(mat.tile_type) as f32)
+                        .collect();
+                    (color32(mat.tile_type.base_color()), values)
+                })
+                .collect();
+            graph(ui, &population);
+
+            ui.label("Changed per tick");
+            let changed = history
+                .samples
+                .iter()
+                .map(|sample| sample.stats.changed as f32)
+                .collect();
+            graph(ui, &[(egui::Color32::LIGHT_BLUE, changed)]);
+
+            ui.label("Tick duration");
+            let durations = history
+                .samples
+                .iter()
+                .map(|sample| sample.tick_duration.as_secs_f32())
+                .collect();
+            graph(ui, &[(egui::Color32::LIGHT_YELLOW, durations)]);
+
+            ui.horizontal(|ui| {
+                if ui.button("Export CSV").clicked() {
+                    history.export_message = Some(match fs::write(STATS_PATH, history.to_csv()) {
+                        Ok(()) => format!("Saved {}", STATS_PATH),
+                        Err(err) => format!("Couldn't save {}: {}", STATS_PATH, err),
+                    });
+                }
+                if let Some(message) = &history.export_message {
+                    ui.label(message);
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::Tile;
+
+    #[test]
+    fn counts_every_type() {
+        let mut board = Board::new(5, 4);
+        let _ = board.set(1, 1, Tile::from_type(TileType::Sand));
+        let _ = board.set(2, 1, Tile::from_type(TileType::Sand));
+        let _ = board.set(3, 2, Tile::from_type(TileType::Water));
+
+        let stats = BoardStats::new(&board, None);
+        assert_eq!(stats.count(TileType::Wall), 14);
+        assert_eq!(stats.count(TileType::Sand), 2);
+        assert_eq!(stats.count(TileType::Water), 1);
+        assert_eq!(stats.count(TileType::None), 3);
+        assert_eq!(stats.count(TileType::Fire), 0);
+        assert_eq!(stats.population.len(), MATERIALS.len());
+        assert_eq!(stats.changed, 0);
+    }
+
+    #[test]
+    fn changes_count_tiles_of_another_type() {
+        let before = Board::new(5, 4);
+        let mut after = before.clone();
+        assert_eq!(changed_tiles(&before, &after), 0);
+
+        // Same type with another colour isn't a change.
+        let _ = after.set(0, 0, Tile::from_type(TileType::Wall));
+        let _ = after.set(1, 1, Tile::from_type(TileType::Sand));
+        let _ = after.set(0, 2, Tile::from_type(TileType::None));
+        assert_eq!(changed_tiles(&before, &after), 2);
+        assert_eq!(BoardStats::new(&after, Some(&before)).changed, 2);
+    }
+
+    #[test]
+    fn csv_rows_match_the_header() {
+        let board = Board::new(5, 4);
+        let mut history = StatsHistory::default();
+        for tick in 0..3 {
+            history.samples.push_back(Sample {
+                tick,
+                stats: BoardStats::new(&board, Some(&board)),
+                tick_duration: Duration::ZERO,
+            });
+        }
+
+        let csv = history.to_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], BoardStats::csv_header());
+        let columns = lines[0].split(',').count();
+        assert_eq!(columns, MATERIALS.len() + 2);
+        for line in &lines[1..] {
+            assert_eq!(line.split(',').count(), columns, "{}", line);
+        }
+        assert!(lines[1].starts_with("0,0,"));
+    }
+}
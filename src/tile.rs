@@ -74,6 +74,24 @@ pub const MATERIALS: &[Material] = &[
     },
 ];
 
+impl TileType {
+    /// The colour tiles of this type are varied from.
+    pub fn base_color(&self) -> Color {
+        let hex = match self {
+            TileType::None => "000000",
+            TileType::Sand => "e7c496",
+            TileType::Wall => "303233",
+            TileType::Rock => "5a5a5a",
+            TileType::Water => "80ade977",
+            TileType::Fire => "f7b538",
+            TileType::Wood => "8C5F33",
+            TileType::Acid => "70ab5d77",
+            TileType::Dirt => "76552b",
+        };
+        Color::hex(hex).unwrap()
+    }
+}
+
 impl Default for Tile {
     fn default() -> Self {
         Self {
@@ -107,7 +125,7 @@ impl Tile {
             },
             TileType::Wall => Tile {
                 tile_type,
-                color: Color::hex("303233").unwrap(),
+                color: tile_type.base_color(),
                 ..Default::default()
             },
             TileType::Water => Tile {
@@ -116,25 +134,25 @@ impl Tile {
                 solid: false,
                 flows: true,
                 piles: true,
-                color: vary_color(Color::hex("80ade977").unwrap()),
+                color: vary_color(tile_type.base_color()),
                 ..Default::default()
             },
             TileType::Rock => Tile {
                 tile_type,
                 gravity: true,
-                color: vary_color(Color::hex("5a5a5a").unwrap()),
+                color: vary_color(tile_type.base_color()),
                 ..Default::default()
             },
             TileType::Dirt => Tile {
                 tile_type,
                 gravity: true,
-                color: vary_color(Color::hex("76552b").unwrap()),
+                color: vary_color(tile_type.base_color()),
                 ..Default::default()
             },
             TileType::Fire => Tile {
                 tile_type,
                 solid: false,
-                color: vary_color(Color::hex("f7b538").unwrap()),
+                color: vary_color(tile_type.base_color()),
                 strength: Some(5),
                 ..Default::default()
             },
@@ -142,7 +160,7 @@ impl Tile {
                 tile_type,
                 gravity: true,
                 flammable: true,
-                color: vary_color(Color::hex("8C5F33").unwrap()),
+                color: vary_color(tile_type.base_color()),
                 ..Default::default()
             },
             TileType::Acid => Tile {
@@ -151,7 +169,7 @@ impl Tile {
                 solid: false,
                 flows: true,
                 piles: true,
-                color: vary_color(Color::hex("70ab5d77").unwrap()),
+                color: vary_color(tile_type.base_color()),
                 ..Default::default()
             },
         }
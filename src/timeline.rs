@@ -0,0 +1,439 @@
+use crate::{
+    board::Board,
+    controls::ui_visible,
+    physics::{physics, Simulation},
+    settings::{Settings, SNAPSHOT_INTERVAL_RANGE, TIMELINE_MEMORY_RANGE},
+    tile::{Tile, TileType},
+};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use std::{collections::VecDeque, mem::size_of, sync::Arc};
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Timeline>()
+            .add_systems(FixedUpdate, take_snapshots.after(physics))
+            .add_systems(Update, timeline_window.run_if(ui_visible));
+    }
+}
+
+/// Snapshots are cut into square chunks this many tiles across, so a chunk nothing
+/// happened in can be shared with the snapshot before.
+const CHUNK_SIZE: usize = 16;
+
+/// What a snapshot keeps of each tile. Colours are picked again on restore.
+type Cell = (TileType, Option<u8>);
+
+/// One chunk's tiles, column by column, as runs of identical cells.
+#[derive(PartialEq, Eq)]
+struct Chunk {
+    runs: Vec<(Cell, u16)>,
+}
+
+impl Chunk {
+    fn new(board: &Board, x0: usize, y0: usize) -> Self {
+        let mut runs: Vec<(Cell, u16)> = Vec::new();
+        for column in board.tiles[x0..(x0 + CHUNK_SIZE).min(board.width())].iter() {
+            for tile in column[y0..(y0 + CHUNK_SIZE).min(board.height())].iter() {
+                let cell = (tile.tile_type, tile.strength);
+                match runs.last_mut() {
+                    Some((last, length)) if *last == cell => *length += 1,
+                    _ => runs.push((cell, 1)),
+                }
+            }
+        }
+        runs.shrink_to_fit();
+        Chunk { runs }
+    }
+
+    fn bytes(&self) -> usize {
+        size_of::<Chunk>() + self.runs.len() * size_of::<(Cell, u16)>()
+    }
+
+    fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.runs
+            .iter()
+            .flat_map(|(cell, length)| std::iter::repeat_n(*cell, *length as usize))
+    }
+}
+
+/// The board as it was on one tick.
+struct Snapshot {
+    tick: u64,
+    width: usize,
+    height: usize,
+    /// Row by row of chunks, starting bottom left.
+    chunks: Vec<Arc<Chunk>>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot, reusing `previous`'s chunks wherever they're unchanged.
+    /// Returns it with the bytes of the chunks that weren't shared.
+    fn new(board: &Board, tick: u64, previous: Option<&Snapshot>) -> (Self, usize) {
+        let (width, height) = (board.width(), board.height());
+        let previous =
+            previous.filter(|previous| (previous.width, previous.height) == (width, height));
+        let mut new_bytes = 0;
+
+        let mut chunks = Vec::new();
+        for y0 in (0..height).step_by(CHUNK_SIZE) {
+            for x0 in (0..width).step_by(CHUNK_SIZE) {
+                let chunk = Chunk::new(board, x0, y0);
+                let shared = previous
+                    .map(|previous| &previous.chunks[chunks.len()])
+                    .filter(|shared| ***shared == chunk);
+                chunks.push(match shared {
+                    Some(shared) => shared.clone(),
+                    None => {
+                        new_bytes += chunk.bytes();
+                        Arc::new(chunk)
+                    }
+                });
+            }
+        }
+
+        let snapshot = Snapshot {
+            tick,
+            width,
+            height,
+            chunks,
+        };
+        let bytes = new_bytes + snapshot.overhead();
+        (snapshot, bytes)
+    }
+
+    fn overhead(&self) -> usize {
+        size_of::<Snapshot>() + self.chunks.len() * size_of::<Arc<Chunk>>()
+    }
+
+    /// Puts the board back the way it was. Only tiles that differ are touched, so the
+    /// rest keep their colours and other plugins see the restore as ordinary edits.
+    fn restore(&self, board: &mut Board) {
+        if (board.width(), board.height()) != (self.width, self.height) {
+            board.replace_tiles(Board::new(self.width, self.height).tiles);
+        }
+
+        let chunks_across = self.width.div_ceil(CHUNK_SIZE);
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let x0 = index % chunks_across * CHUNK_SIZE;
+            let y0 = index / chunks_across * CHUNK_SIZE;
+            let chunk_height = CHUNK_SIZE.min(self.height - y0);
+
+            for (offset, (tile_type, strength)) in chunk.cells().enumerate() {
+                let (x, y) = (x0 + offset / chunk_height, y0 + offset % chunk_height);
+                let current = &board.tiles[x][y];
+                if current.tile_type != tile_type || current.strength != strength {
+                    let mut tile = Tile::from_type(tile_type);
+                    tile.strength = strength;
+                    let _ = board.set(x, y, tile);
+                }
+            }
+        }
+    }
+}
+
+/// Snapshots of the board every `timeline.interval` ticks, oldest first, dropping the
+/// oldest once they use more than `timeline.memory_mb`.
+#[derive(Resource, Default)]
+pub struct Timeline {
+    snapshots: VecDeque<Snapshot>,
+    /// Estimated bytes used by the snapshots, counting shared chunks once.
+    bytes: usize,
+    /// The snapshot last jumped to and the simulation's tick at the time. Anything
+    /// after it is forgotten once the simulation moves on from there.
+    restored: Option<(usize, u64)>,
+    /// The simulation's tick when the next snapshot is due.
+    next_tick: u64,
+}
+
+impl Timeline {
+    /// Forgets every snapshot, for when the board is replaced with an unrelated one.
+    pub fn clear(&mut self) {
+        *self = Timeline::default();
+    }
+
+    fn push(&mut self, board: &Board, tick: u64) {
+        let (snapshot, bytes) = Snapshot::new(board, tick, self.snapshots.back());
+        self.snapshots.push_back(snapshot);
+        self.bytes += bytes;
+    }
+
+    /// Puts the board back to snapshot `index`. The snapshots after it are kept until
+    /// the simulation moves on from `tick`, so the player can still jump forwards.
+    fn jump_to(&mut self, index: usize, board: &mut Board, tick: u64, interval: u64) {
+        self.snapshots[index].restore(board);
+        self.restored = Some((index, tick));
+        self.next_tick = tick + interval;
+    }
+
+    /// Frees a snapshot, and any chunks only it was using.
+    fn forget(&mut self, snapshot: Snapshot) {
+        self.bytes -= snapshot.overhead();
+        for chunk in snapshot.chunks.iter() {
+            if Arc::strong_count(chunk) == 1 {
+                self.bytes -= chunk.bytes();
+            }
+        }
+    }
+
+    fn truncate(&mut self, length: usize) {
+        while self.snapshots.len() > length {
+            let snapshot = self.snapshots.pop_back().unwrap();
+            self.forget(snapshot);
+        }
+    }
+
+    /// Drops the oldest snapshots until they fit in `max_bytes`, always keeping one.
+    fn trim(&mut self, max_bytes: usize) {
+        while self.bytes > max_bytes && self.snapshots.len() > 1 {
+            let snapshot = self.snapshots.pop_front().unwrap();
+            self.forget(snapshot);
+        }
+    }
+}
+
+fn take_snapshots(
+    board: Res<Board>,
+    simulation: Res<Simulation>,
+    settings: Res<Settings>,
+    mut timeline: ResMut<Timeline>,
+) {
+    if let Some((index, tick)) = timeline.restored {
+        if simulation.tick == tick {
+            return;
+        }
+        // Resumed from an earlier point, so what came after it didn't happen.
+        timeline.truncate(index + 1);
+        timeline.restored = None;
+    }
+
+    if simulation.tick >= timeline.next_tick {
+        timeline.push(&board, simulation.tick);
+        timeline.next_tick = simulation.tick + settings.timeline.interval;
+        timeline.trim(settings.timeline.memory_mb * 1024 * 1024);
+    }
+}
+
+fn timeline_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut timeline: ResMut<Timeline>,
+    mut board: ResMut<Board>,
+    mut simulation: ResMut<Simulation>,
+) {
+    let mut open = settings.ui.timeline;
+    egui::Window::new("Timeline")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            if timeline.snapshots.is_empty() {
+                ui.label("Waiting for the first snapshot.");
+                return;
+            }
+
+            let newest = timeline.snapshots.len() - 1;
+            let mut index = timeline.restored.map_or(newest, |(index, _)| index);
+            let tick = timeline.snapshots[index].tick;
+            let slider = egui::Slider::new(&mut index, 0..=newest)
+                .show_value(false)
+                .text(format!("Tick {}", tick));
+            if ui.add(slider).changed() {
+                let interval = settings.timeline.interval;
+                timeline.jump_to(index, &mut board, simulation.tick, interval);
+                simulation.paused = true;
+            }
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(simulation.paused, egui::Button::new("Resume"))
+                    .clicked()
+                {
+                    simulation.paused = false;
+                }
+                ui.label(format!(
+                    "{} snapshots, {:.1} of {} MB",
+                    timeline.snapshots.len(),
+                    timeline.bytes as f64 / (1024. * 1024.),
+                    settings.timeline.memory_mb
+                ));
+            });
+
+            ui.add(
+                egui::Slider::new(&mut settings.timeline.interval, SNAPSHOT_INTERVAL_RANGE)
+                    .text("Ticks between snapshots"),
+            );
+            ui.add(
+                egui::Slider::new(&mut settings.timeline.memory_mb, TIMELINE_MEMORY_RANGE)
+                    .text("Memory (MB)"),
+            );
+        });
+    settings.ui.timeline = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    /// A board of random tiles, some with strengths their material wouldn't start with.
+    fn random_board(width: usize, height: usize, seed: u64) -> Board {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let types = [
+            TileType::None,
+            TileType::Sand,
+            TileType::Water,
+            TileType::Fire,
+        ];
+        let mut board = Board::new(width, height);
+        for x in 0..width {
+            for y in 0..height {
+                let mut tile = Tile::from_type(types[rng.gen_range(0..types.len())]);
+                if rng.gen_bool(0.1) {
+                    tile.strength = Some(rng.gen());
+                }
+                board.tiles[x][y] = tile;
+            }
+        }
+        board
+    }
+
+    fn cells(board: &Board) -> Vec<Vec<Cell>> {
+        board
+            .tiles
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|tile| (tile.tile_type, tile.strength))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// What `timeline.bytes` should be: every snapshot, and every chunk once however
+    /// many snapshots share it.
+    fn counted_bytes(timeline: &Timeline) -> usize {
+        let mut seen = HashSet::new();
+        let mut bytes = 0;
+        for snapshot in timeline.snapshots.iter() {
+            bytes += snapshot.overhead();
+            for chunk in snapshot.chunks.iter() {
+                if seen.insert(Arc::as_ptr(chunk)) {
+                    bytes += chunk.bytes();
+                }
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn restoring_gives_back_the_board() {
+        // Neither side a multiple of the chunk size, so the edge chunks are partial.
+        let board = random_board(37, 21, 0);
+        let (snapshot, _) = Snapshot::new(&board, 0, None);
+
+        let mut restored = random_board(37, 21, 1);
+        snapshot.restore(&mut restored);
+        assert_eq!(cells(&restored), cells(&board));
+
+        let mut resized = Board::new(5, 50);
+        snapshot.restore(&mut resized);
+        assert_eq!(cells(&resized), cells(&board));
+    }
+
+    #[test]
+    fn unchanged_chunks_are_shared() {
+        let mut board = random_board(40, 20, 0);
+        let (first, _) = Snapshot::new(&board, 0, None);
+        board.tiles[20][3] = Tile::from_type(TileType::Wood);
+        let (second, bytes) = Snapshot::new(&board, 1, Some(&first));
+
+        // Chunks are numbered row by row, three across.
+        let changed = 1;
+        for (index, (a, b)) in first.chunks.iter().zip(second.chunks.iter()).enumerate() {
+            assert_eq!(Arc::ptr_eq(a, b), index != changed, "chunk {}", index);
+        }
+        assert_eq!(bytes, second.chunks[changed].bytes() + second.overhead());
+
+        // Nothing is shared with a board of a different size.
+        let (resized, _) = Snapshot::new(&random_board(40, 21, 0), 2, Some(&second));
+        assert!(!Arc::ptr_eq(&resized.chunks[0], &second.chunks[0]));
+    }
+
+    #[test]
+    fn trimming_drops_the_oldest_snapshots() {
+        let mut timeline = Timeline::default();
+        let mut board = random_board(40, 40, 0);
+        for tick in 0..6 {
+            board.tiles[tick as usize][0] = Tile::from_type(TileType::Rock);
+            timeline.push(&board, tick);
+            assert_eq!(timeline.bytes, counted_bytes(&timeline));
+        }
+
+        // Any less memory than they use now and the oldest has to go.
+        timeline.trim(timeline.bytes - 1);
+        let ticks: Vec<u64> = timeline
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.tick)
+            .collect();
+        assert_eq!(ticks, [1, 2, 3, 4, 5]);
+        assert_eq!(timeline.bytes, counted_bytes(&timeline));
+
+        // However little memory there is, the newest snapshot stays.
+        timeline.trim(0);
+        assert_eq!(timeline.snapshots.len(), 1);
+        assert_eq!(timeline.snapshots[0].tick, 5);
+        assert_eq!(timeline.bytes, counted_bytes(&timeline));
+
+        timeline.clear();
+        assert!(timeline.snapshots.is_empty());
+        assert_eq!(timeline.bytes, 0);
+    }
+
+    #[test]
+    fn resuming_from_an_earlier_snapshot_forgets_the_later_ones() {
+        let mut app = App::new();
+        let mut settings = Settings::default();
+        settings.timeline.interval = 1;
+        app.insert_resource(random_board(20, 20, 0))
+            .insert_resource(settings)
+            .init_resource::<Simulation>()
+            .init_resource::<Timeline>()
+            .add_systems(Update, take_snapshots);
+
+        let mut boards = Vec::new();
+        for tick in 0..4 {
+            app.world.resource_mut::<Simulation>().tick = tick;
+            app.world.resource_mut::<Board>().tiles[1][tick as usize] =
+                Tile::from_type(TileType::Sand);
+            boards.push(cells(app.world.resource::<Board>()));
+            app.update();
+        }
+        assert_eq!(app.world.resource::<Timeline>().snapshots.len(), 4);
+
+        app.world
+            .resource_scope(|world, mut timeline: Mut<Timeline>| {
+                let mut board = world.resource_mut::<Board>();
+                timeline.jump_to(1, &mut board, 3, 1);
+            });
+        assert_eq!(cells(app.world.resource::<Board>()), boards[1]);
+
+        // Paused on the restored snapshot, the later ones can still be jumped to.
+        app.update();
+        assert_eq!(app.world.resource::<Timeline>().snapshots.len(), 4);
+
+        app.world.resource_mut::<Simulation>().tick = 4;
+        app.update();
+        let timeline = app.world.resource::<Timeline>();
+        let ticks: Vec<u64> = timeline
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.tick)
+            .collect();
+        assert_eq!(ticks, [0, 1, 4]);
+        assert_eq!(timeline.bytes, counted_bytes(timeline));
+    }
+}
@@ -0,0 +1,161 @@
+//! Starts `particles --headless --control <socket>` and talks to it like a script would.
+#![cfg(unix)]
+
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A fresh directory for one test, also used as the config directory so the user's
+/// settings are never read or written.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("particles-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn particles(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_particles"));
+    command
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// Kills the app when the test ends, passing or not.
+struct App(Child);
+
+impl Drop for App {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+struct Connection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Connection {
+    /// Connects once the app is listening, failing if it exits or takes longer than 30 seconds.
+    fn open(app: &mut App, socket: &Path) -> Self {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            if let Ok(stream) = UnixStream::connect(socket) {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(30)))
+                    .unwrap();
+                return Connection {
+                    reader: BufReader::new(stream.try_clone().unwrap()),
+                    writer: stream,
+                };
+            }
+            if let Some(status) = app.0.try_wait().unwrap() {
+                panic!("the app exited with {} before listening", status);
+            }
+            assert!(Instant::now() < deadline, "the app never started listening");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn send(&mut self, request: Value) -> Value {
+        writeln!(self.writer, "{}", request).unwrap();
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+#[test]
+fn headless_app_answers_commands() {
+    let dir = temp_dir("remote");
+    let socket = dir.join("control.sock");
+    let mut app = App(particles(
+        &dir,
+        &[
+            "--headless",
+            "--width=20",
+            "--height=10",
+            "--control",
+            socket.to_str().unwrap(),
+        ],
+    )
+    .spawn()
+    .unwrap());
+    let mut connection = Connection::open(&mut app, &socket);
+
+    let response = connection.send(json!({"command": "pause"}));
+    assert_eq!(response, json!({"ok": true, "paused": true}));
+
+    let response = connection.send(json!({"command": "stats"}));
+    assert_eq!(response["width"], 20);
+    assert_eq!(response["height"], 10);
+    let tick = response["tick"].as_u64().unwrap();
+
+    let response = connection.send(json!({
+        "command": "fill_rect", "x": 2, "y": 1, "width": 3, "height": 2, "tile_type": "Water"
+    }));
+    assert_eq!(response, json!({"ok": true}));
+    let response = connection.send(json!({"command": "set", "x": 8, "y": 5, "tile_type": "Sand"}));
+    assert_eq!(response, json!({"ok": true}));
+
+    let response = connection.send(json!({
+        "command": "get_region", "x": 1, "y": 0, "width": 5, "height": 3
+    }));
+    assert_eq!(response["rows"], json!([".~~~.", ".~~~.", "#####"]));
+
+    let response = connection.send(json!({"command": "step", "ticks": 100}));
+    assert_eq!(response, json!({"ok": true, "tick": tick + 100}));
+
+    // The sand has landed on the floor, maybe pushed aside by the spreading water.
+    let response = connection.send(json!({
+        "command": "get_region", "x": 1, "y": 1, "width": 18, "height": 1
+    }));
+    assert!(response["rows"][0].as_str().unwrap().contains('s'));
+
+    let response = connection.send(json!({"command": "stats"}));
+    assert_eq!(response["population"]["Water"], 6);
+    assert_eq!(response["population"]["Sand"], 1);
+
+    let response = connection.send(json!({"command": "set", "x": 20, "y": 0, "tile_type": "Sand"}));
+    assert_eq!(response["ok"], false);
+    let response = connection.send(json!({"command": "explode"}));
+    assert_eq!(response["ok"], false);
+
+    let saved = dir.join("saved.txt");
+    let response = connection.send(json!({"command": "save", "path": saved}));
+    assert_eq!(response, json!({"ok": true}));
+    assert_eq!(fs::read_to_string(&saved).unwrap().lines().count(), 10);
+    let response = connection.send(json!({"command": "load", "path": saved}));
+    assert_eq!(response, json!({"ok": true, "width": 20, "height": 10}));
+
+    drop(connection);
+    drop(app);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn control_path_that_isnt_a_socket_is_left_alone() {
+    let dir = temp_dir("remote-not-a-socket");
+    let file = dir.join("notes.txt");
+    fs::write(&file, "keep me").unwrap();
+
+    let output = particles(&dir, &["--headless", "--control", file.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("isn't a socket"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+    fs::remove_dir_all(dir).unwrap();
+}